@@ -0,0 +1,22 @@
+use std::process::Command;
+
+/// Captures the build information reported by `GET /version`: the git commit the service is built
+/// from (`GIT_SHA`, `unknown` outside of a git checkout) and the build time (`BUILT_AT`, RFC 3339).
+fn main() {
+    let git_sha = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=BUILT_AT={}", chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+
+    // Rebuild when another commit is checked out or made.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-changed=build.rs");
+}
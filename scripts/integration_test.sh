@@ -0,0 +1,98 @@
+#!/usr/bin/env bash
+# End-to-end check of the database path: starts a throwaway PostgreSQL container, runs the
+# server against it (migrations included), submits a full order over HTTP, and checks that
+# it was written to all four tables. The same path is covered without docker by the Rust tests
+# in tests/db.rs (`DATABASE_URL=... cargo test --test db -- --ignored`); this script additionally
+# checks the built binary with its command line and HTTP server.
+#
+# Usage: scripts/integration_test.sh
+#
+# Requires docker, psql, and curl. To run against an already running PostgreSQL instead of a
+# container, set PGHOST, PGPORT, PGUSER, PGPASSWORD, and PGDATABASE (the tables are created
+# if missing, the test order is deleted afterwards).
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+HTTP_ADDR="127.0.0.1:${HTTP_PORT:-3999}"
+ORDER_UID="it-$(date +%s)-$$"
+CONTAINER=""
+SERVER_PID=""
+
+cleanup() {
+    [ -n "$SERVER_PID" ] && kill "$SERVER_PID" 2>/dev/null && wait "$SERVER_PID" 2>/dev/null || true
+    [ -n "$CONTAINER" ] && docker rm -f "$CONTAINER" >/dev/null || true
+}
+trap cleanup EXIT
+
+fail() {
+    echo "FAIL: $*" >&2
+    exit 1
+}
+
+if [ -z "${PGHOST:-}" ]; then
+    export PGHOST=127.0.0.1 PGUSER=postgres PGPASSWORD=postgres PGDATABASE=wb
+    CONTAINER=$(docker run -d --rm -e POSTGRES_PASSWORD="$PGPASSWORD" -e POSTGRES_DB="$PGDATABASE" \
+        -p 127.0.0.1::5432 postgres:15)
+    export PGPORT=$(docker port "$CONTAINER" 5432/tcp | head -n1 | cut -d: -f2)
+
+    echo "Waiting for PostgreSQL in container ${CONTAINER:0:12} on port $PGPORT"
+    for _ in $(seq 60); do
+        psql -c "SELECT 1" >/dev/null 2>&1 && break
+        sleep 1
+    done
+fi
+psql -c "SELECT 1" >/dev/null || fail "PostgreSQL is not reachable"
+
+cargo build --quiet
+./target/debug/wb-rest-order \
+    --host-name "$PGHOST port=${PGPORT:-5432}" -u "$PGUSER" -d "$PGDATABASE" \
+    --socket-addr "$HTTP_ADDR" --log-config /dev/null/none --flush-interval-secs 0 &
+SERVER_PID=$!
+
+for _ in $(seq 30); do
+    curl -sf "http://$HTTP_ADDR/health" >/dev/null && break
+    sleep 1
+done
+
+status=$(curl -s -o /dev/null -w "%{http_code}" -X POST "http://$HTTP_ADDR/order" \
+    -H "Content-Type: application/json" -d @- <<JSON
+{
+  "order_uid": "$ORDER_UID",
+  "track_number": "WBILMTESTTRACK",
+  "entry": "WBIL",
+  "delivery": {"name": "Test Testov", "phone": "+9720000000", "zip": "2639809", "city": "Kiryat Mozkin",
+               "address": "Ploshad Mira 15", "region": "Kraiot", "email": "test@gmail.com"},
+  "payment": {"transaction": "$ORDER_UID", "request_id": "", "currency": "USD", "provider": "wbpay",
+              "amount": 1917, "payment_dt": 1637907727, "bank": "alpha", "delivery_cost": 1500,
+              "goods_total": 417, "custom_fee": 0},
+  "items": [
+    {"chrt_id": 9934930, "track_number": "WBILMTESTTRACK", "price": 453, "rid": "ab4219087a764ae0btest",
+     "name": "Mascaras", "sale": 30, "size": "0", "total_price": 317, "nm_id": 2389212,
+     "brand": "Vivienne Sabo", "status": 202},
+    {"chrt_id": 9934931, "track_number": "WBILMTESTTRACK", "price": 100, "rid": "ab4219087a764ae0btest2",
+     "name": "Lipstick", "sale": 0, "size": "0", "total_price": 100, "nm_id": 2389213,
+     "brand": "Vivienne Sabo", "status": 202}
+  ],
+  "locale": "en", "internal_signature": "", "customer_id": "test", "delivery_service": "meest",
+  "shardkey": "9", "sm_id": 99, "date_created": "2021-11-26T06:22:19Z", "oof_shard": "1"
+}
+JSON
+)
+[ "$status" = "201" ] || fail "POST /order returned $status"
+
+# Orders are buffered in memory; stopping the server flushes them to the database.
+kill -INT "$SERVER_PID"
+wait "$SERVER_PID" || true
+SERVER_PID=""
+
+count() {
+    psql -tAc "SELECT COUNT(*) FROM $1 WHERE $2 = '$ORDER_UID'"
+}
+[ "$(count orders order_uid)" = "1" ] || fail "order row is missing"
+[ "$(count deliveries order_uid)" = "1" ] || fail "delivery row is missing"
+[ "$(count payments transaction_id)" = "1" ] || fail "payment row is missing"
+[ "$(count items order_uid)" = "2" ] || fail "expected 2 item rows"
+
+psql -qc "DELETE FROM orders WHERE order_uid = '$ORDER_UID'"
+echo "OK: order $ORDER_UID was written to orders, deliveries, payments, and items"
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_postgres::error::{Error as PostgresError, SqlState};
+use crate::retry;
+
+/// Stops sending work to a database that keeps failing, so that an outage doesn't make every
+/// request wait for its own timeout and pile more load onto the database.
+///
+/// After `threshold` consecutive failures the circuit opens and database access fails fast for
+/// `cool_down`. Then the circuit is half-open: requests go through again, and the next recorded
+/// outcome either closes the circuit or opens it for another `cool_down`.
+/// - `threshold`: Number of consecutive failures that open the circuit; `0` disables the breaker.
+/// - `cool_down`: How long the circuit stays open before recovery is tested.
+/// - `inner`: The current state of the circuit.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cool_down: Duration,
+    inner: Mutex<Inner>,
+}
+
+/// The state of a `CircuitBreaker`.
+/// - `failures`: Number of consecutive failures recorded while the circuit is closed.
+/// - `opened_at`: When the circuit was last opened, or `None` if it is closed.
+/// - `half_open`: Whether the cool-down has passed and recovery is being tested.
+#[derive(Default)]
+struct Inner {
+    failures: u32,
+    opened_at: Option<Instant>,
+    half_open: bool,
+}
+
+/// The state of the circuit as reported by `GET /health`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// The database is used normally.
+    Closed,
+    /// Database access fails fast until the cool-down is over.
+    Open,
+    /// The cool-down is over and the next outcome decides whether the circuit closes again.
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit that opens after `threshold` consecutive failures for `cool_down`;
+    /// a `threshold` of `0` never opens it.
+    pub fn new(threshold: u32, cool_down: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cool_down,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Checks whether the database may be accessed, moving an open circuit whose cool-down is
+    /// over to the half-open state.
+    ///
+    /// # Returns
+    /// `Ok(())` if the access is allowed, or `Err` with the time until the cool-down is over.
+    pub fn check(&self) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+
+        match inner.opened_at {
+            Some(opened_at) if !inner.half_open => {
+                let elapsed = opened_at.elapsed();
+                if elapsed < self.cool_down {
+                    return Err(self.cool_down - elapsed);
+                }
+                inner.half_open = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a successful database operation, closing the circuit.
+    ///
+    /// # Returns
+    /// `true` if the circuit was open or half-open before.
+    pub fn record_success(&self) -> bool {
+        let previous = std::mem::take(&mut *self.inner.lock().unwrap());
+        previous.opened_at.is_some()
+    }
+
+    /// Records a failed database operation. Only failures that indicate an unhealthy database
+    /// count (see `is_outage`); errors caused by the data, such as a violated constraint, are ignored.
+    ///
+    /// # Returns
+    /// `true` if the failure opened the circuit.
+    pub fn record_failure(&self, e: &PostgresError) -> bool {
+        if self.threshold == 0 || !is_outage(e) {
+            return false;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.half_open {
+            inner.half_open = false;
+            inner.opened_at = Some(Instant::now());
+            return true;
+        }
+        if inner.opened_at.is_some() {
+            return false;
+        }
+
+        inner.failures += 1;
+        if inner.failures >= self.threshold {
+            inner.opened_at = Some(Instant::now());
+            return true;
+        }
+        false
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+
+        match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(_) if inner.half_open => CircuitState::HalfOpen,
+            Some(opened_at) if opened_at.elapsed() >= self.cool_down => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+}
+
+/// Whether `e` means that the database is unhealthy rather than that the operation was wrong:
+/// the transient errors that are retried (see `retry::is_transient`) and statements cancelled
+/// by `statement_timeout`.
+fn is_outage(e: &PostgresError) -> bool {
+    retry::is_transient(e) || e.code() == Some(&SqlState::QUERY_CANCELED)
+}
@@ -0,0 +1,56 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+/// The `order_uid`s of the most recently accepted orders, so that a resubmission of an order
+/// that already left the queue is recognized without a round trip to the database.
+///
+/// Once `capacity` uids are remembered, the oldest one is forgotten.
+/// - `capacity`: Maximum number of remembered uids; `0` disables the window.
+/// - `inner`: The remembered uids.
+pub struct DedupWindow {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+/// The state of a `DedupWindow`.
+/// - `uids`: The remembered uids, for lookups.
+/// - `arrival`: The remembered uids in the order they were accepted, oldest first.
+#[derive(Default)]
+struct Inner {
+    uids: HashSet<String>,
+    arrival: VecDeque<String>,
+}
+
+impl DedupWindow {
+    /// Creates an empty window remembering up to `capacity` uids; `0` disables it.
+    pub fn new(capacity: usize) -> Self {
+        DedupWindow {
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Returns whether `order_uid` is among the remembered uids.
+    pub fn contains(&self, order_uid: &str) -> bool {
+        self.inner.lock().unwrap().uids.contains(order_uid)
+    }
+
+    /// Remembers `order_uid`, forgetting the oldest uid if the window is full.
+    pub fn insert(&self, order_uid: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.uids.insert(order_uid.to_string()) {
+            return;
+        }
+        inner.arrival.push_back(order_uid.to_string());
+
+        if inner.arrival.len() > self.capacity {
+            if let Some(forgotten) = inner.arrival.pop_front() {
+                inner.uids.remove(&forgotten);
+            }
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::time::Duration;
+use thiserror::Error;
+use tokio_postgres::error::{DbError, SqlState};
+use crate::state::{AddOrderError, DatabaseError, QueueFullError};
+
+/// How long clients are asked to wait before resubmitting an order refused because the queue is full.
+pub const QUEUE_FULL_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// An error returned by a handler, rendered as `{"error": {"code": "...", "message": "..."}}`
+/// with the matching status code, so that clients can handle every failure the same way.
+/// `InvalidOrder` additionally lists every violated rule in `error.details`.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    /// The request body is not valid JSON of the expected type (`invalid_body`).
+    #[error("{}", .0.body_text())]
+    InvalidBody(#[from] JsonRejection),
+    /// The query string can't be parsed (`invalid_query`).
+    #[error("{}", .0.body_text())]
+    InvalidQuery(#[from] QueryRejection),
+    /// A query parameter or header has a value that is not accepted, e.g. a non-positive `limit`
+    /// (`invalid_query`).
+    #[error("{0}")]
+    InvalidParameter(String),
+    /// The order violates the rules checked by `Order::validate` (`invalid_order`).
+    #[error("the order is invalid: {}", .0.join("; "))]
+    InvalidOrder(Vec<String>),
+    /// The requested resource doesn't exist (`not_found`).
+    #[error("{0}")]
+    NotFound(String),
+    /// The request contradicts itself or the state of the resource (`conflict`).
+    #[error("{0}")]
+    Conflict(String),
+    /// A database operation failed (`database_error`, `database_timeout` and `database_unavailable`
+    /// for a `503`, or `constraint_violation` and `invalid_data` for a `422`).
+    #[error("database error: {0}")]
+    Database(#[from] DatabaseError),
+    /// The answer depends on a database that can't be read, so it is unknown rather than wrong
+    /// (`database_unavailable`).
+    #[error("{0}")]
+    DatabaseUnavailable(String),
+    /// Orders are not accepted because the service is in read-only mode (`read_only`).
+    #[error("the service is in read-only mode for maintenance, orders are not accepted; try again later")]
+    ReadOnly,
+    /// The order queue is full and can't be flushed (`queue_full`).
+    #[error(transparent)]
+    QueueFull(#[from] QueueFullError),
+    /// The order can't be recorded in the journal (`journal_error`).
+    #[error("journal error: {0}")]
+    Journal(#[source] std::io::Error),
+}
+
+impl From<AddOrderError> for ApiError {
+    fn from(e: AddOrderError) -> Self {
+        match e {
+            AddOrderError::QueueFull(e) => ApiError::QueueFull(e),
+            AddOrderError::Journal(e) => ApiError::Journal(e),
+            AddOrderError::Database(e) => ApiError::Database(e),
+        }
+    }
+}
+
+impl ApiError {
+    /// Returns the HTTP status code of the response.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            // Malformed or mistyped JSON is a client error; other rejections (e.g. a missing
+            // `Content-Type`) keep their own status.
+            ApiError::InvalidBody(JsonRejection::JsonDataError(_) | JsonRejection::JsonSyntaxError(_)) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidBody(rejection) => rejection.status(),
+            ApiError::InvalidQuery(_) | ApiError::InvalidParameter(_) | ApiError::InvalidOrder(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Database(e) => database_error_status(e),
+            ApiError::DatabaseUnavailable(_) | ApiError::ReadOnly | ApiError::QueueFull(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Journal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Returns the machine-readable error code put into `error.code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidBody(_) => "invalid_body",
+            ApiError::InvalidQuery(_) | ApiError::InvalidParameter(_) => "invalid_query",
+            ApiError::InvalidOrder(_) => "invalid_order",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Database(DatabaseError::CircuitOpen(_)) | ApiError::DatabaseUnavailable(_) => "database_unavailable",
+            ApiError::Database(_) if self.status() == StatusCode::SERVICE_UNAVAILABLE => "database_timeout",
+            ApiError::Database(e) if data_error(e).is_some_and(|db| db.code().code().starts_with("23")) => "constraint_violation",
+            ApiError::Database(e) if data_error(e).is_some() => "invalid_data",
+            ApiError::Database(_) => "database_error",
+            ApiError::ReadOnly => "read_only",
+            ApiError::QueueFull(_) => "queue_full",
+            ApiError::Journal(_) => "journal_error",
+        }
+    }
+
+    /// Returns the human-readable message put into `error.message`. Database errors are not
+    /// described in detail, the handlers log them instead, except for the ones caused by the
+    /// submitted data (see `data_error_message`).
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::InvalidOrder(_) => "the order is invalid".to_string(),
+            ApiError::Database(DatabaseError::CircuitOpen(_)) => {
+                "the database is unavailable after repeated failures, try again later".to_string()
+            }
+            ApiError::Database(_) if self.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                "the database didn't respond in time, try again later".to_string()
+            }
+            ApiError::Database(e) => data_error_message(e).unwrap_or_else(|| "failed to access the database".to_string()),
+            ApiError::QueueFull(_) => "the order queue is full, try again later".to_string(),
+            ApiError::Journal(_) => "failed to record the order".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Renders the error like `into_response`, with `fields` added to the body next to `error`,
+    /// e.g. the summary of a batch of orders that was only partly accepted.
+    pub fn into_response_with(self, fields: Map<String, Value>) -> Response {
+        let mut error = json!({"code": self.code(), "message": self.message()});
+        if let ApiError::InvalidOrder(violations) = &self {
+            error["details"] = json!(violations);
+        }
+        let mut body = fields;
+        body.insert("error".to_string(), error);
+
+        let mut response = (self.status(), Json(body)).into_response();
+        match self {
+            ApiError::QueueFull(_) => {
+                response.headers_mut().insert(header::RETRY_AFTER, QUEUE_FULL_RETRY_AFTER.as_secs().into());
+            }
+            ApiError::Database(DatabaseError::CircuitOpen(retry_after)) => {
+                // Round up, so that clients don't come back right before the circuit is half-open.
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response.headers_mut().insert(header::RETRY_AFTER, seconds.into());
+            }
+            _ => {}
+        }
+        response
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.into_response_with(Map::new())
+    }
+}
+
+/// Chooses the status of a response to a failed database operation: `StatusCode::SERVICE_UNAVAILABLE`
+/// if the database didn't respond in time (a statement hit `statement_timeout` or connecting timed out)
+/// or the circuit breaker is open, so that clients know to retry later, `StatusCode::UNPROCESSABLE_ENTITY`
+/// if the data was rejected by the database (see `data_error`), and `StatusCode::INTERNAL_SERVER_ERROR` otherwise.
+pub fn database_error_status(e: &DatabaseError) -> StatusCode {
+    let e = match e {
+        DatabaseError::Postgres(e) => e,
+        DatabaseError::CircuitOpen(_) => return StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let connect_timed_out = e.source()
+        .and_then(|source| source.downcast_ref::<std::io::Error>())
+        .is_some_and(|source| source.kind() == std::io::ErrorKind::TimedOut);
+
+    if e.code() == Some(&SqlState::QUERY_CANCELED) || connect_timed_out {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else if e.as_db_error().is_some_and(is_data_error) {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Returns the error reported by the database if it was caused by the data rather than by the
+/// service: a violated integrity constraint such as a duplicate key, a foreign key, or a `CHECK`
+/// (SQLSTATE class `23`), or a value the column can't hold (class `22`, e.g. out of range).
+///
+/// The inserts of `AppState` skip rows that are already stored (`ON CONFLICT DO NOTHING`), so a
+/// resubmitted order never causes a duplicate key; such errors come from constraints added to
+/// the tables outside of `schema.sql`.
+fn data_error(e: &DatabaseError) -> Option<&DbError> {
+    match e {
+        DatabaseError::Postgres(e) => e.as_db_error().filter(|db| is_data_error(db)),
+        DatabaseError::CircuitOpen(_) => None,
+    }
+}
+
+/// Whether the SQLSTATE of `db` is in class `22` or `23`, see `data_error`.
+fn is_data_error(db: &DbError) -> bool {
+    matches!(&db.code().code()[..2], "22" | "23")
+}
+
+/// Describes an error caused by the data (see `data_error`) for the client, such as the violated
+/// constraint and the offending key.
+///
+/// # Returns
+/// The description, or `None` if the error is not caused by the data.
+pub fn data_error_message(e: &DatabaseError) -> Option<String> {
+    let db = data_error(e)?;
+    Some(match db.detail() {
+        Some(detail) => format!("{}: {detail}", db.message()),
+        None => db.message().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_postgres::config::SslMode;
+    use tokio_postgres::{Config, NoTls};
+
+    /// Makes tokio-postgres report a database error with the SQLSTATE `code` and `detail`, as it
+    /// would for PostgreSQL, by answering its startup message with an `ErrorResponse` over an
+    /// in-memory stream.
+    async fn db_error(code: &'static str, detail: &'static str) -> DatabaseError {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let backend = async move {
+            let mut startup = [0; 1024];
+            let _ = server.read(&mut startup).await.unwrap();
+
+            let mut fields = Vec::new();
+            for (field, value) in [
+                (b'S', "ERROR"),
+                (b'C', code),
+                (b'M', "rejected by the test"),
+                (b'D', detail),
+            ] {
+                fields.push(field);
+                fields.extend_from_slice(value.as_bytes());
+                fields.push(0);
+            }
+            fields.push(0);
+            let mut message = vec![b'E'];
+            message.extend_from_slice(&(fields.len() as i32 + 4).to_be_bytes());
+            message.extend_from_slice(&fields);
+            server.write_all(&message).await.unwrap();
+            server
+        };
+        let mut config = Config::new();
+        config.user("test").ssl_mode(SslMode::Disable);
+
+        match tokio::join!(config.connect_raw(client, NoTls), backend).0 {
+            Ok(_) => panic!("the connection should have been refused"),
+            Err(e) => DatabaseError::Postgres(e),
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicate_key_is_a_constraint_violation() {
+        let error = ApiError::from(db_error("23505", "Key (order_uid)=(a) already exists.").await);
+
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code(), "constraint_violation");
+        assert_eq!(
+            error.message(),
+            "rejected by the test: Key (order_uid)=(a) already exists."
+        );
+    }
+
+    #[tokio::test]
+    async fn foreign_key_violation_is_a_constraint_violation() {
+        let error = ApiError::from(
+            db_error(
+                "23503",
+                "Key (order_uid)=(a) is not present in table \"orders\".",
+            )
+            .await,
+        );
+
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code(), "constraint_violation");
+    }
+
+    #[tokio::test]
+    async fn value_out_of_range_is_invalid_data() {
+        let error = ApiError::from(db_error("22003", "").await);
+
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code(), "invalid_data");
+    }
+
+    #[tokio::test]
+    async fn canceled_statement_is_a_timeout() {
+        let error = ApiError::from(db_error("57014", "").await);
+
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "database_timeout");
+    }
+
+    #[tokio::test]
+    async fn other_database_errors_are_internal() {
+        let error = ApiError::from(db_error("42P01", "").await);
+
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.code(), "database_error");
+        assert_eq!(error.message(), "failed to access the database");
+    }
+
+    #[test]
+    fn open_circuit_is_unavailable() {
+        let error = ApiError::from(DatabaseError::CircuitOpen(Duration::from_millis(1500)));
+
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "database_unavailable");
+        let response = error.into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
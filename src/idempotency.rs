@@ -0,0 +1,169 @@
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The responses to recent requests carrying an `Idempotency-Key`, so that a client retrying
+/// such a request gets the original response instead of having it processed again.
+///
+/// A key is reserved while its first request is processed and keeps the response for `ttl`
+/// afterwards; expired keys are forgotten the next time a key is reserved. Once `capacity`
+/// responses are stored, storing another one forgets the oldest, so that clients sending a new
+/// key with every request can't grow the cache without bound.
+/// - `ttl`: How long a response is kept.
+/// - `capacity`: Maximum number of stored responses.
+/// - `inner`: The reserved keys and the stored responses.
+pub struct IdempotencyCache {
+    ttl: Duration,
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+/// The state of an `IdempotencyCache`.
+/// - `entries`: The reserved keys with their stored responses, if complete.
+/// - `expiry`: The keys of the stored responses with the time they were stored, oldest first.
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    expiry: VecDeque<(Instant, String)>,
+}
+
+/// A reserved key.
+enum Entry {
+    /// The first request with the key is still being processed.
+    InFlight,
+    /// The response to the first request with the key, stored at the given time.
+    Done(Instant, StoredResponse),
+}
+
+/// A response kept by an `IdempotencyCache`.
+/// - `status`, `headers`, `body`: The parts of the response.
+#[derive(Clone)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// The outcome of `IdempotencyCache::reserve`.
+pub enum Reservation<'a> {
+    /// The key is new; the request has to be processed and its response passed to `Pending::complete`.
+    Pending(Pending<'a>),
+    /// A request with the key was already processed; this is its response.
+    Replay(StoredResponse),
+    /// A request with the key is still being processed.
+    InFlight,
+}
+
+/// A key reserved for a request being processed. Dropping it without calling `complete`, e.g.
+/// because the client went away, releases the key, so that a retry is processed.
+pub struct Pending<'a> {
+    cache: &'a IdempotencyCache,
+    key: Option<String>,
+}
+
+impl IdempotencyCache {
+    /// Creates an empty cache keeping up to `capacity` responses for `ttl`.
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        IdempotencyCache {
+            ttl,
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Looks up `key` and reserves it if it is new, forgetting the expired keys first.
+    pub fn reserve(&self, key: &str) -> Reservation<'_> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        while let Some((stored_at, _)) = inner.expiry.front() {
+            if stored_at.elapsed() < self.ttl {
+                break;
+            }
+            inner.forget_oldest();
+        }
+
+        match inner.entries.get(key) {
+            Some(Entry::Done(_, response)) => Reservation::Replay(response.clone()),
+            Some(Entry::InFlight) => Reservation::InFlight,
+            None => {
+                inner.entries.insert(key.to_string(), Entry::InFlight);
+                Reservation::Pending(Pending { cache: self, key: Some(key.to_string()) })
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Forgets the oldest stored response.
+    fn forget_oldest(&mut self) {
+        let Some((stored_at, key)) = self.expiry.pop_front() else {
+            return;
+        };
+        // The key may have been stored again since; only the entry stored at this time is forgotten.
+        if matches!(self.entries.get(&key), Some(Entry::Done(at, _)) if *at == stored_at) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+impl Pending<'_> {
+    /// Finishes the request: stores `response` for later repeats, or releases the key if it is
+    /// `None`, e.g. because the request failed and may be retried.
+    pub fn complete(mut self, response: Option<StoredResponse>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut inner = self.cache.inner.lock().unwrap();
+
+        match response {
+            Some(response) => {
+                let now = Instant::now();
+                inner.entries.insert(key.clone(), Entry::Done(now, response));
+                inner.expiry.push_back((now, key));
+                while inner.expiry.len() > self.cache.capacity {
+                    inner.forget_oldest();
+                }
+            }
+            None => {
+                inner.entries.remove(&key);
+            }
+        }
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cache.inner.lock().unwrap().entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reserves `key` and stores an empty response for it.
+    fn store(cache: &IdempotencyCache, key: &str) {
+        let Reservation::Pending(pending) = cache.reserve(key) else {
+            panic!("{key} should be new");
+        };
+        pending.complete(Some(StoredResponse { status: StatusCode::CREATED, headers: HeaderMap::new(), body: Bytes::new() }));
+    }
+
+    #[test]
+    fn oldest_response_is_forgotten_beyond_capacity() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        for key in ["a", "b", "c"] {
+            store(&cache, key);
+        }
+
+        assert!(matches!(cache.reserve("b"), Reservation::Replay(_)));
+        assert!(matches!(cache.reserve("c"), Reservation::Replay(_)));
+        assert!(matches!(cache.reserve("a"), Reservation::Pending(_)));
+        assert_eq!(cache.inner.lock().unwrap().expiry.len(), 2);
+    }
+}
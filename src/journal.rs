@@ -0,0 +1,109 @@
+use crate::order::Order;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// An append-only file of the orders that were accepted but not yet saved to the database,
+/// so that they survive a crash of the service and not only a graceful shutdown.
+///
+/// Every order is appended as a line of JSON and synced to disk before it is acknowledged.
+/// After a flush, the journal is rewritten with only the orders that are still pending
+/// (see `Journal::checkpoint`), so it never grows beyond the unsaved part of the queue.
+/// - `path`: The location of the journal file.
+/// - `file`: The journal file, opened for appending.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+/// A line of the journal. `Order::received_at` is not deserialized from request bodies,
+/// so it is kept next to the order.
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    received_at: Option<DateTime<Utc>>,
+    order: &'a Order,
+}
+
+/// A line of the journal as read back by `Journal::open`.
+#[derive(Deserialize)]
+struct Entry {
+    received_at: Option<DateTime<Utc>>,
+    order: Order,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating the file if it doesn't exist, and reads the orders
+    /// recorded in it. A later entry for an `order_uid` replaces the earlier ones, as the order was
+    /// corrected in between; lines that can't be parsed, such as one torn by a crash, are skipped
+    /// with a warning.
+    ///
+    /// # Returns
+    /// The journal and its orders in the order they were first recorded, or an `io::Error`.
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<Order>)> {
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+
+        let mut orders: Vec<Order> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (number, line) in BufReader::new(&file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let Entry { received_at, mut order } = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping line {} of the journal {}: {}", number + 1, path.display(), e);
+                    continue;
+                }
+            };
+            order.received_at = received_at;
+
+            match positions.get(&order.order_uid) {
+                Some(&position) => orders[position] = order,
+                None => {
+                    positions.insert(order.order_uid.clone(), orders.len());
+                    orders.push(order);
+                }
+            }
+        }
+
+        Ok((Journal { path: path.to_path_buf(), file }, orders))
+    }
+
+    /// Appends `order` to the journal and waits until it is written to disk.
+    pub fn append(&mut self, order: &Order) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&EntryRef { received_at: order.received_at, order })?;
+        line.push(b'\n');
+
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+
+    /// Replaces the contents of the journal with `pending`, the orders that are still not saved
+    /// to the database. The new contents are written to a temporary file that then replaces the
+    /// journal, so a crash in between leaves either the old or the new journal behind.
+    pub fn checkpoint<'a>(&mut self, pending: impl Iterator<Item = &'a Order>) -> io::Result<()> {
+        let mut pending = pending.peekable();
+        if pending.peek().is_none() {
+            self.file.set_len(0)?;
+            return self.file.sync_data();
+        }
+
+        let temporary = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        for order in pending {
+            serde_json::to_writer(&mut writer, &EntryRef { received_at: order.received_at, order })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_data()?;
+
+        std::fs::rename(&temporary, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+//! A service accepting orders over HTTP, buffering them in memory, and persisting them to
+//! PostgreSQL. The binary in `main.rs` wires the modules together; they are exposed as a library
+//! so that the integration tests in `tests/` can build the same state and router.
+
+pub mod state;
+pub mod order;
+pub mod routes;
+pub mod cli;
+pub mod pool;
+pub mod metrics;
+pub mod migrations;
+pub mod logging;
+pub mod rate_limit;
+pub mod webhook;
+pub mod error;
+pub mod seed;
+pub mod retry;
+pub mod journal;
+pub mod listener;
+pub mod read_cache;
+pub mod circuit_breaker;
+pub mod dedup;
+pub mod idempotency;
+pub mod memory_store;
+pub mod snapshot;
@@ -0,0 +1,155 @@
+use axum::Router;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::watch;
+#[cfg(unix)]
+use {
+    hyper_util::rt::{TokioExecutor, TokioIo},
+    hyper_util::server::conn::auto::Builder,
+    hyper_util::service::TowerToHyperService,
+    log::{debug, warn},
+    std::os::unix::fs::FileTypeExt,
+    tokio::net::{UnixListener, UnixStream},
+    tokio::task::JoinSet,
+};
+
+/// An address the web server listens on, as given to `--socket-addr`.
+#[derive(Clone, Debug)]
+pub enum ListenAddr {
+    /// A TCP socket address, such as `127.0.0.1:3000` or `[::]:3000`.
+    Tcp(SocketAddr),
+    /// A Unix domain socket, given as `unix:/path/to.sock`.
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("expected a socket path after `unix:`".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => s.parse().map(ListenAddr::Tcp).map_err(|e| format!("{e}; expected `IP:PORT` or `unix:/path/to.sock`")),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Serves `app` on the Unix domain socket at `path` until `shutdown` turns `true`, for sidecar
+/// deployments that talk to the service over a socket file instead of TCP.
+///
+/// A socket file left behind by a previous run that is no longer listening is removed before
+/// binding. On shutdown no more connections are accepted, the socket file is removed, and the
+/// open connections are given `grace_period` to finish their requests before they are closed.
+///
+/// # Parameters
+/// - `path`: The location of the socket file.
+/// - `app`: The router serving the requests.
+/// - `shutdown`: Turns `true` when the server has to stop.
+/// - `grace_period`: How long in-flight requests may take to complete after the shutdown.
+///
+/// # Returns
+/// `Ok(())` after a shutdown, or an `io::Error` if the socket can't be bound.
+#[cfg(unix)]
+pub async fn serve_unix(
+    path: &Path,
+    app: Router,
+    mut shutdown: watch::Receiver<bool>,
+    grace_period: Duration,
+) -> io::Result<()> {
+    remove_stale_socket(path).await?;
+    let listener = UnixListener::bind(path)?;
+
+    let mut connections = JoinSet::new();
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a connection on {}: {}", path.display(), e);
+                    continue;
+                }
+            },
+            _ = stopped(&mut shutdown) => break,
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+        let mut shutdown = shutdown.clone();
+        connections.spawn(async move {
+            let builder = Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            tokio::pin!(connection);
+
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = stopped(&mut shutdown) => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(e) = result {
+                debug!("Unix socket connection closed with an error: {}", e);
+            }
+        });
+    }
+
+    drop(listener);
+    if let Err(e) = std::fs::remove_file(path) {
+        warn!("Failed to remove the socket file {}: {}", path.display(), e);
+    }
+
+    let drained = tokio::time::timeout(grace_period, async {
+        while connections.join_next().await.is_some() {}
+    });
+    if drained.await.is_err() {
+        warn!("Closing {} connections on {} that didn't finish in time", connections.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Waits until `shutdown` turns `true`, or its sender is gone.
+#[cfg(unix)]
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|&stop| stop).await;
+}
+
+/// Removes the socket file at `path` if it is left over from a previous run, i.e. nothing accepts
+/// connections on it anymore. A socket that is still in use and a file that is not a socket are
+/// kept and reported as `io::ErrorKind::AddrInUse`, so that another instance or unrelated data is
+/// never clobbered.
+#[cfg(unix)]
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} exists and is not a socket", path.display())));
+    }
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use by another process", path.display())));
+    }
+
+    warn!("Removing the stale socket file {}", path.display());
+    std::fs::remove_file(path)
+}
+
+/// Unix domain sockets are not available on this platform, so serving on one always fails.
+#[cfg(not(unix))]
+pub async fn serve_unix(path: &Path, _: Router, _: watch::Receiver<bool>, _: Duration) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("can't listen on {}: Unix sockets are not supported", path.display())))
+}
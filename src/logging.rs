@@ -0,0 +1,185 @@
+use clap::ValueEnum;
+use log::{info, warn, LevelFilter};
+use log4rs::append::console::ConsoleAppender;
+use log4rs::config::{Appender, Config, Deserializers, RawConfig, Root};
+use log4rs::encode::{json::JsonEncoder, pattern::PatternEncoder, Encode};
+use log4rs::Handle;
+use serde_yaml::{Mapping, Value};
+use std::error::Error;
+use std::path::Path;
+
+/// The pattern of the text log records used by the built-in configuration.
+const DEFAULT_PATTERN: &str = "{d} {l} {t} {m}{n}";
+
+/// The format of the log records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Use the encoders from the configuration file (human-readable lines by default).
+    Text,
+    /// Write every record as a single JSON object with the time, level, target, and message.
+    Json,
+}
+
+///
+/// Initializes logging for the application.
+///
+/// This function loads the `log4rs` configuration from the YAML file at `config_path`.
+/// With `LogFormat::Json`, the encoder of every appender from the file is replaced
+/// with the JSON encoder, so that log aggregators receive one JSON object per line.
+///
+/// If the file doesn't exist, a built-in configuration is used instead: records of
+/// level info and above are written to stdout.
+///
+/// `level` overrides the level of the root logger from the configuration, e.g. for a quick
+/// debugging session; the loggers with their own level in the file keep it.
+///
+/// # Returns
+/// The `Handle` to pass to `reload` on success, or an error if the configuration file can't be
+/// read or is invalid, or if a logger has already been initialized.
+pub fn init(config_path: &Path, format: LogFormat, level: Option<LevelFilter>) -> Result<Handle, Box<dyn Error>> {
+    let fallback = !config_path.exists();
+    let config = build_config(config_path, format, level)?;
+    let root_level = config.root().level();
+
+    let handle = log4rs::init_config(config)?;
+
+    if fallback {
+        warn!("Logging config {} not found, logging to stdout", config_path.display());
+    }
+    info!("Logging at {} level{}", root_level, if level.is_some() { " (overridden)" } else { "" });
+    Ok(handle)
+}
+
+/// Builds the configuration again with the arguments given to `init` and swaps it in through
+/// `handle`, so that the file appenders reopen their files, e.g. after logrotate moved them away.
+///
+/// A record logged during the swap is written by either the old or the new appenders, so none
+/// are lost; the old files are flushed and closed once the last such record is written.
+///
+/// # Returns
+/// `Ok(())` on success, or an error if the configuration file can't be read or is invalid,
+/// in which case the current configuration stays in place.
+pub fn reload(handle: &Handle, config_path: &Path, format: LogFormat, level: Option<LevelFilter>) -> Result<(), Box<dyn Error>> {
+    handle.set_config(build_config(config_path, format, level)?);
+    Ok(())
+}
+
+/// Builds the configuration from the file at `config_path`, or the built-in one if it doesn't exist.
+fn build_config(config_path: &Path, format: LogFormat, level: Option<LevelFilter>) -> Result<Config, Box<dyn Error>> {
+    if config_path.exists() {
+        load_config(config_path, format, level)
+    } else {
+        default_config(format, level.unwrap_or(LevelFilter::Info))
+    }
+}
+
+/// Builds the built-in configuration: a single stdout appender at `level`.
+fn default_config(format: LogFormat, level: LevelFilter) -> Result<Config, Box<dyn Error>> {
+    let encoder: Box<dyn Encode> = match format {
+        LogFormat::Text => Box::new(PatternEncoder::new(DEFAULT_PATTERN)),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    };
+    let stdout = ConsoleAppender::builder().encoder(encoder).build();
+
+    Ok(Config::builder()
+        .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .build(Root::builder().appender("stdout").build(level))?)
+}
+
+/// Reads the YAML configuration file and builds the `log4rs` configuration in the given format,
+/// with the root logger at `level` if it is given.
+fn load_config(config_path: &Path, format: LogFormat, level: Option<LevelFilter>) -> Result<Config, Box<dyn Error>> {
+    let mut yaml: Value = serde_yaml::from_str(&std::fs::read_to_string(config_path)?)?;
+
+    if format == LogFormat::Json {
+        use_json_encoders(&mut yaml);
+    }
+
+    let raw: RawConfig = serde_yaml::from_value(yaml)?;
+    let (appenders, errors) = raw.appenders_lossy(&Deserializers::default());
+    if !errors.is_empty() {
+        return Err(Box::new(errors));
+    }
+
+    let root = raw.root();
+    let root = Root::builder()
+        .appenders(root.appenders().iter().cloned())
+        .build(level.unwrap_or(root.level()));
+
+    Ok(Config::builder()
+        .appenders(appenders)
+        .loggers(raw.loggers())
+        .build(root)?)
+}
+
+/// Sets the encoder of every appender in the raw YAML configuration to `kind: json`.
+fn use_json_encoders(yaml: &mut Value) {
+    let Some(appenders) = yaml.get_mut("appenders").and_then(Value::as_mapping_mut) else {
+        return;
+    };
+
+    for (_, appender) in appenders.iter_mut() {
+        if let Some(appender) = appender.as_mapping_mut() {
+            let mut encoder = Mapping::new();
+            encoder.insert("kind".into(), "json".into());
+            appender.insert("encoder".into(), Value::Mapping(encoder));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn reload_reopens_the_log_file_without_losing_records() {
+        let dir = std::env::temp_dir().join(format!("wb-rest-order-logging-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let log_path = dir.join("app.log");
+        let rotated_path = dir.join("app.log.1");
+        let config_path = dir.join("log_cfg.yaml");
+        let config = format!(
+            "appenders:\n  file:\n    kind: file\n    path: {:?}\n    encoder:\n      pattern: \"{{m}}{{n}}\"\nroot:\n  level: info\n  appenders:\n    - file\n",
+            log_path,
+        );
+        fs::write(&config_path, config).unwrap();
+        let handle = init(&config_path, LogFormat::Text, None).unwrap();
+
+        // Keep writing records until a few were written after the reload.
+        let reloaded = Arc::new(AtomicBool::new(false));
+        let writer = std::thread::spawn({
+            let reloaded = reloaded.clone();
+            move || {
+                let mut written = 0;
+                let mut after_reload = 0;
+                while after_reload < 100 {
+                    if reloaded.load(Ordering::Relaxed) {
+                        after_reload += 1;
+                    }
+                    info!("record {written}");
+                    written += 1;
+                }
+                written
+            }
+        });
+        // Rotate like logrotate while records are being written: move the file away, then reopen.
+        while fs::metadata(&log_path).map_or(0, |metadata| metadata.len()) == 0 {
+            std::thread::yield_now();
+        }
+        fs::rename(&log_path, &rotated_path).unwrap();
+        reload(&handle, &config_path, LogFormat::Text, None).unwrap();
+        reloaded.store(true, Ordering::Relaxed);
+        let written = writer.join().unwrap();
+
+        let rotated = fs::read_to_string(&rotated_path).unwrap();
+        let reopened = fs::read_to_string(&log_path).unwrap();
+        let records: Vec<&str> = rotated.lines().chain(reopened.lines()).filter(|line| line.starts_with("record ")).collect();
+        let expected: Vec<String> = (0..written).map(|i| format!("record {i}")).collect();
+        assert_eq!(records, expected);
+        assert!(reopened.contains(&format!("record {}", written - 1)), "records after the reload should go to the reopened file");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
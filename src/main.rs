@@ -1,87 +1,276 @@
-mod state;
-mod order;
-mod routes;
-mod cli;
 
-use axum::Router;
+use axum::{extract::DefaultBodyLimit, middleware, Router};
 use std::sync::Arc;
-use cli::CLIArgs;
-use state::AppState;
-use log::info;
+use wb_rest_order::cli::CLIArgs;
+use wb_rest_order::listener::{self, ListenAddr};
+use wb_rest_order::state::AppState;
+use wb_rest_order::rate_limit::RateLimiter;
+use wb_rest_order::{logging, routes, seed};
+use log::{info, warn, error as cry};
 use std::net::SocketAddr;
-use clap::Parser;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use axum_server::Handle;
+use axum::{body::Body, http::{Request, Response}};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+/// How long in-flight requests are given to complete once shutdown has been requested.
+const GRACEFUL_SHUTDOWN_PERIOD: Duration = Duration::from_secs(10);
 
 /// 
 /// The main function that runs the server. 
 /// 
 /// This function serves as the entry point of the application, where it:
-/// - Initializes logging
 /// - Parses command-line arguments using the `clap` crate to configure the server
+/// - Builds the Tokio runtime with `--worker-threads` worker threads
+/// - Initializes logging
 /// - Sets up the application state, including a connection to PostgreSQL
 /// - Configures Axum routes and starts the Axum web server.
 ///
 /// # Steps
-/// 1. **Initialize logging**: This step configures logging using the `log4rs` crate, loading the configuration from a YAML file.
-/// 2. **Parse CLI arguments**: The `clap`-generated `CLIArgs` struct is used to handle command-line parameters, such as the socket address and database credentials.
-/// 3. **Initialize app state**: An `AppState` struct is created, which includes the max capacity for caching orders and database client connections.
+/// 1. **Parse CLI arguments**: The `clap`-generated `CLIArgs` struct is used to handle command-line parameters, such as the socket address and database credentials.
+///    Settings missing on the command line are taken from the `--config` file, if given.
+///    The Tokio runtime is then built and the remaining steps run on it (see `run`).
+/// 2. **Initialize logging**: This step configures logging using the `log4rs` crate, loading the YAML configuration
+///    from `--log-config` and writing plain text or JSON records depending on `--log-format`.
+///    On SIGHUP the configuration is loaded again, so that the log files are reopened.
+/// 3. **Initialize app state**: An `AppState` struct is created, which includes the max capacity for caching orders and a pool of database connections.
+///    With `--no-db` there is no database, and the orders are stored in memory instead.
+///    The orders from `--seed-file`, if given, are queued next.
 /// 4. **Set up Axum routes**: Axum routes are defined in a separate `routes` module, and the app's routes are registered to handle HTTP requests.
-/// 5. **Start the Axum server**: The server is bound to the provided socket address and starts handling incoming requests.
+/// 5. **Start the Axum server**: A server is bound to every provided socket address and starts handling incoming requests.
+/// 6. **Shut down gracefully**: On Ctrl-C or SIGTERM the server stops accepting connections and
+///    all buffered orders are flushed to the database before exiting.
 ///
 /// # Panics
 /// The function will panic if:
-/// - The provided socket address is invalid.
-/// - The server fails to start (e.g., port already in use).
-#[tokio::main]
-async fn main() {
-    // Initialize logging from a configuration file
-    init_logging();
-
+/// - The Tokio runtime can't be built.
+/// - The logging configuration can't be loaded.
+/// - A server fails to start (e.g., port already in use).
+fn main() {
     // Parse command-line arguments
-    let args = CLIArgs::parse();  // CLIArgs struct is generated from clap to capture user input
+    let args = CLIArgs::parse_with_config();  // CLIArgs struct is generated from clap to capture user input, merged with the --config file
+
+    // Build the multi-threaded runtime, with one worker per CPU unless configured otherwise
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = args.worker_threads {
+        runtime.worker_threads(worker_threads.get());
+    }
+    let runtime = runtime
+        .enable_all()
+        .build()
+        .expect("Failed to build the Tokio runtime");
+
+    runtime.block_on(run(args));
+}
+
+///
+/// Runs the service on the Tokio runtime built by `main`, from initializing logging
+/// until the buffered orders are flushed on shutdown.
+async fn run(args: CLIArgs) {
+    // Initialize logging from a configuration file, with the root level from the flag or RUST_LOG
+    let log_level = args.resolve_log_level().unwrap_or_else(|e| e.exit());
+    let logger = logging::init(&args.log_config, args.log_format, log_level)
+        .expect("Failed to initialize logging");
 
-    // Parse and validate the socket address
-    let socket_addr: SocketAddr = args.socket_addr.parse()
-        .expect("Invalid socket address");  // Exit if the address is malformed
+    // Reopen the log files on SIGHUP, e.g. after logrotate moved them away
+    #[cfg(unix)]
+    tokio::spawn(reload_logging_on_hangup(logger, args.log_config.clone(), args.log_format, log_level));
+    #[cfg(not(unix))]
+    drop(logger);
+
+    // Resolve the database connection string, or the parameters and the password from the flag, the environment, or a file
+    let database = args.resolve_database().unwrap_or_else(|e| e.exit());
 
     // Create the app state, including database connection and order queue
-    let state = Arc::new(
-        AppState::new(
-            args.cache_size,  // The maximum capacity for the runtime order queue
-            &args.host_name,  // Database host (e.g., localhost)
-            &args.user_name,  // Database username
-            &args.db_name,    // Database name
-            &args.password    // Database password
-        )
-        .await
-    );
+    let config = args.app_state_config(database);
+    let state = match AppState::new(&config).await {
+        Ok(state) => Arc::new(state),
+        Err(e) => {
+            cry!("Failed to start: {}", e);
+            eprintln!("Failed to start: {e}");
+            std::process::exit(1);  // Exit without a panic trace, the reason is already logged
+        }
+    };
+
+    // Fill the queue with the orders from the seed file, if given
+    if let Some(seed_file) = &args.seed_file {
+        seed::load(&state, seed_file).await;
+    }
+
+    // Periodically persist the orders that stay in the queue for too long
+    if args.flush_interval_secs > 0 {
+        state.start_flusher(Duration::from_secs(args.flush_interval_secs));
+    }
+
+    // Periodically copy the orders that are not saved yet to the snapshot file, if given
+    if args.snapshot_path.is_some() {
+        state.start_snapshots(Duration::from_secs(args.snapshot_interval_secs));
+    }
 
     // Setup the Axum application with the routes and shared application state
-    let app = Router::new()
+    let mut app = Router::new()
         .merge(routes::handle_order())  // Register routes from the routes module
-        .with_state(state);  // Attach the shared application state
+        .with_state(state.clone());  // Attach the shared application state
+
+    // Answer repeated submissions with the same Idempotency-Key from the stored response, if enabled;
+    // registered first, so that authentication and rate limiting run before it
+    if args.idempotency_ttl_secs > 0 {
+        app = app.route_layer(middleware::from_fn_with_state(state.clone(), routes::idempotent_submissions));
+    }
+
+    // Require a bearer token on every route except the health check, if a key is configured
+    match args.resolve_api_key() {
+        Some(api_key) => {
+            app = app.route_layer(middleware::from_fn_with_state(Arc::<str>::from(api_key), routes::require_api_key));
+        }
+        None => warn!("No API key configured, the endpoints are not authenticated"),
+    }
+
+    // Limit the rate of writes from every client, if enabled
+    if args.rate_limit_per_sec > 0 {
+        let limiter = Arc::new(RateLimiter::new(args.rate_limit_per_sec));
+        app = app.route_layer(middleware::from_fn_with_state(limiter, routes::rate_limit_writes));
+    }
+
+    // Answer CORS preflights and add CORS headers for the allowed origins, if configured
+    if !args.cors_origins.is_empty() {
+        app = app.layer(routes::cors_layer(&args.cors_origins));
+    }
 
-    // Log that the server is starting and display the listening address
-    info!("Listening on {}", socket_addr);
+    let app = app
+        .layer(DefaultBodyLimit::max(args.max_body_bytes))  // Reject oversized bodies with 413
+        // Layers run bottom to top: assign an `X-Request-Id`, log the request, copy the id to the response
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http()
+            .on_request(|request: &Request<Body>, _: &Span| {
+                info!("request_id={} {} {}", routes::request_id(request.headers()), request.method(), request.uri());
+            })
+            .on_response(|response: &Response<Body>, latency: Duration, _: &Span| {
+                info!("request_id={} {} in {:?}", routes::request_id(response.headers()), response.status(), latency);
+            }))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
-    // Bind the server to the socket address and start it
-    axum_server::bind(socket_addr)
-        .serve(app.into_make_service())  // Serve the app with Axum
-        .await
-        .expect("Failed to start server");  // Exit if the server fails to bind or start
+    // Stop accepting connections once a shutdown signal arrives
+    let handle = Handle::new();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(shutdown_signal(handle.clone(), shutdown_tx));
+
+    // Bind a server to every socket address; they share the routes, the state, and the shutdown signal
+    let mut servers = JoinSet::new();
+    for socket_addr in &args.socket_addr {
+        // Log that the server is starting and display the listening address
+        info!("Listening on {} (request bodies up to {} bytes)", socket_addr, args.max_body_bytes);
+
+        let socket_addr = socket_addr.clone();
+        match &socket_addr {
+            ListenAddr::Tcp(addr) => {
+                let server = axum_server::bind(*addr)
+                    .handle(handle.clone())
+                    .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>());  // Serve the app with Axum, exposing peer addresses
+                servers.spawn(async move { (socket_addr, server.await) });
+            }
+            ListenAddr::Unix(path) => {
+                let (path, app, shutdown) = (path.clone(), app.clone(), shutdown_rx.clone());
+                servers.spawn(async move {
+                    let result = listener::serve_unix(&path, app, shutdown, GRACEFUL_SHUTDOWN_PERIOD).await;  // Serve the app over a socket file
+                    (socket_addr, result)
+                });
+            }
+        }
+    }
+
+    // Wait until every server has stopped
+    while let Some(result) = servers.join_next().await {
+        let (socket_addr, result) = result.expect("Server task panicked");
+        if let Err(e) = result {
+            panic!("Failed to start server on {socket_addr}: {e}");  // Exit if the server fails to bind or start
+        }
+    }
+
+    // Persist the orders that are still buffered in memory, trying the database even if the circuit is open,
+    // but don't let a slow database hold up the exit for longer than the shutdown timeout
+    state.reset_circuit();
+    let shutdown_timeout = Duration::from_secs(args.shutdown_timeout_secs);
+    let flushed = if shutdown_timeout.is_zero() {
+        Ok(state.flush_all().await)
+    } else {
+        tokio::time::timeout(shutdown_timeout, state.flush_all()).await
+    };
+    match flushed {
+        Ok(Ok(flushed)) => info!("Flushed {} orders to the database on shutdown", flushed),
+        Ok(Err(e)) => cry!("Failed to flush orders on shutdown: {}", e),
+        Err(_) => {
+            let kept = match (&args.journal_path, &args.snapshot_path) {
+                (Some(_), _) => "they stay in the journal and are queued again on the next start",
+                (None, Some(_)) => "they are kept in the snapshot and queued again on the next start",
+                (None, None) => "they are lost",
+            };
+            cry!(
+                "Gave up flushing orders on shutdown after {:?}, {} orders remain unflushed: {}",
+                shutdown_timeout, state.count_pending().await, kept
+            );
+        }
+    }
+    // Leave a snapshot with only the orders that couldn't be saved, if any
+    state.write_snapshot().await;
 }
 
-/// 
-/// Initializes logging for the application.
 ///
-/// This function loads the logging configuration from a YAML file located at
-/// `src/resources/logging/log_cfg.yaml`. The `log4rs` crate is used to configure 
-/// logging, allowing different levels of log outputs such as error, info, debug, etc.
-/// 
-/// # Panics
-/// If the logging configuration file cannot be found or loaded correctly, this function
-/// will panic and the application will not start.
-fn init_logging() {
-    // Load the logging configuration from a file
-    log4rs::init_file("src/resources/logging/log_cfg.yaml",
-        Default::default()).unwrap();
+/// Reloads the logging configuration through `logger` whenever SIGHUP arrives, so that the log
+/// files are reopened after an external tool such as logrotate moved them away, without a restart.
+/// A configuration that can't be loaded is reported and the current one is kept.
+#[cfg(unix)]
+async fn reload_logging_on_hangup(
+    logger: log4rs::Handle,
+    config_path: std::path::PathBuf,
+    format: logging::LogFormat,
+    level: Option<log::LevelFilter>,
+) {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("Failed to install SIGHUP handler");
+
+    while hangup.recv().await.is_some() {
+        match logging::reload(&logger, &config_path, format, level) {
+            Ok(()) => info!("SIGHUP received, reopened the log files"),
+            Err(e) => warn!("SIGHUP received, but the logging config {} can't be loaded: {}", config_path.display(), e),
+        }
+    }
+}
+
+///
+/// Waits for Ctrl-C or SIGTERM and then triggers a graceful shutdown of the TCP servers through
+/// `handle` and of the Unix socket servers through `shutdown`.
+///
+/// In-flight requests are given `GRACEFUL_SHUTDOWN_PERIOD` to complete before their
+/// connections are closed.
+async fn shutdown_signal(handle: Handle, shutdown: watch::Sender<bool>) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, stopping the server");
+    handle.graceful_shutdown(Some(GRACEFUL_SHUTDOWN_PERIOD));
+    shutdown.send_replace(true);
 }
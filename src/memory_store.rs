@@ -0,0 +1,102 @@
+use crate::order::Order;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// The stored orders in the in-memory mode (`--no-db`), standing in for the database in tests
+/// and demos. Orders flushed from the queue are saved here instead, and the read endpoints query
+/// it instead of the tables. Nothing survives a restart.
+///
+/// Once `capacity` orders are stored, saving another one drops the oldest.
+/// - `capacity`: Maximum number of stored orders.
+/// - `inner`: The stored orders.
+pub struct MemoryStore {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+/// The state of a `MemoryStore`.
+/// - `orders`: The stored orders by `order_uid`.
+/// - `arrival`: The `order_uid`s in the order they were saved, oldest first.
+#[derive(Default)]
+struct Inner {
+    orders: HashMap<String, Order>,
+    arrival: VecDeque<String>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store holding up to `capacity` orders.
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Saves `orders`, dropping the oldest stored orders if the store is full. Saving an order
+    /// whose `order_uid` is already stored is a no-op, as in `AppState::save_to_db`.
+    pub fn save(&self, orders: &[&Order]) {
+        let mut inner = self.inner.lock().unwrap();
+
+        for order in orders {
+            if inner.orders.contains_key(&order.order_uid) {
+                continue;
+            }
+            inner.orders.insert(order.order_uid.clone(), (*order).clone());
+            inner.arrival.push_back(order.order_uid.clone());
+
+            if inner.arrival.len() > self.capacity {
+                if let Some(dropped) = inner.arrival.pop_front() {
+                    inner.orders.remove(&dropped);
+                }
+            }
+        }
+    }
+
+    /// Replaces the stored order with the `order_uid` of `order`, keeping the time it was received.
+    ///
+    /// # Returns
+    /// `true` if the order was replaced, `false` if it is not stored.
+    pub fn update(&self, order: &Order) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+
+        match inner.orders.get_mut(&order.order_uid) {
+            Some(stored) => {
+                let received_at = stored.received_at;
+                *stored = order.clone();
+                stored.received_at = received_at;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the stored order with `order_uid`, if any.
+    pub fn get(&self, order_uid: &str) -> Option<Order> {
+        self.inner.lock().unwrap().orders.get(order_uid).cloned()
+    }
+
+    /// Returns whether an order with `order_uid` is stored.
+    pub fn contains(&self, order_uid: &str) -> bool {
+        self.inner.lock().unwrap().orders.contains_key(order_uid)
+    }
+
+    /// Returns the number of stored orders.
+    pub fn count(&self) -> usize {
+        self.inner.lock().unwrap().orders.len()
+    }
+
+    /// Returns a copy of the stored orders matching `filter`, in no particular order.
+    pub fn find(&self, filter: impl Fn(&Order) -> bool) -> Vec<Order> {
+        let inner = self.inner.lock().unwrap();
+
+        inner.orders.values().filter(|order| filter(order)).cloned().collect()
+    }
+
+    /// Returns a copy of the stored orders matching `filter`, most recent (by `date_created`) first,
+    /// like the `ORDER BY o.date_created DESC` queries of `AppState`.
+    pub fn newest(&self, filter: impl Fn(&Order) -> bool) -> Vec<Order> {
+        let mut orders = self.find(filter);
+        orders.sort_by(|a, b| b.date_created.cmp(&a.date_created));
+        orders
+    }
+}
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the flush latency histogram buckets.
+const FLUSH_DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Upper bounds (in seconds) of the insert latency histogram buckets; a single insert is much
+/// faster than a whole flush.
+const INSERT_DURATION_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// A table written by `AppState::save_to_db`, used to label the insert latency histograms.
+#[derive(Clone, Copy, Debug)]
+pub enum Table {
+    Orders,
+    Deliveries,
+    Payments,
+    Items,
+}
+
+impl Table {
+    /// All tables, in the order their histograms are rendered.
+    const ALL: [Table; 4] = [Table::Orders, Table::Deliveries, Table::Payments, Table::Items];
+
+    /// Returns the name of the table, used as the `table` label.
+    fn name(self) -> &'static str {
+        match self {
+            Table::Orders => "orders",
+            Table::Deliveries => "deliveries",
+            Table::Payments => "payments",
+            Table::Items => "items",
+        }
+    }
+}
+
+/// Runtime metrics of the service, exposed in the Prometheus text format.
+/// - `orders_received`: Counter of orders received over HTTP.
+/// - `orders_flushed`: Counter of orders persisted to the database.
+/// - `queue_depth`: Gauge of the current number of orders in the in-memory queue.
+/// - `last_flush_at`: When orders were last persisted, in milliseconds since the Unix epoch; `0` if never.
+/// - `flush_duration`: Histogram of the time taken by flushes of the queue.
+/// - `insert_duration`: Histograms of the time taken by the inserts of a saved order, one per `Table`.
+pub struct Metrics {
+    orders_received: AtomicU64,
+    orders_flushed: AtomicU64,
+    queue_depth: AtomicU64,
+    last_flush_at: AtomicI64,
+    flush_duration: Histogram,
+    insert_duration: [Histogram; 4],
+}
+
+/// A Prometheus histogram with fixed buckets.
+/// - `bounds`: Upper bounds of the buckets, in seconds.
+/// - `bucket_counts`: Number of observations falling into each bucket
+///   (non-cumulative; the sums are computed when rendering).
+/// - `sum_micros`: Sum of all observations, in microseconds.
+/// - `count`: Total number of observations.
+struct Histogram {
+    bounds: &'static [f64],
+    bucket_counts: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts an order received over HTTP.
+    pub fn inc_orders_received(&self) {
+        self.orders_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts `n` orders persisted to the database just now.
+    pub fn add_orders_flushed(&self, n: usize) {
+        self.orders_flushed.fetch_add(n as u64, Ordering::Relaxed);
+        self.last_flush_at.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Returns the number of orders received over HTTP.
+    pub fn orders_received(&self) -> u64 {
+        self.orders_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of orders persisted to the database.
+    pub fn orders_flushed(&self) -> u64 {
+        self.orders_flushed.load(Ordering::Relaxed)
+    }
+
+    /// Returns when orders were last persisted to the database, or `None` if none were yet.
+    pub fn last_flush_at(&self) -> Option<DateTime<Utc>> {
+        match self.last_flush_at.load(Ordering::Relaxed) {
+            0 => None,
+            millis => DateTime::from_timestamp_millis(millis),
+        }
+    }
+
+    /// Sets the current number of orders in the in-memory queue.
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth as u64, Ordering::Relaxed);
+    }
+
+    /// Records the duration of a flush of the queue.
+    pub fn observe_flush_duration(&self, duration: Duration) {
+        self.flush_duration.observe(duration);
+    }
+
+    /// Records the duration of the insert of a saved order's rows into `table`.
+    pub fn observe_insert_duration(&self, table: Table, duration: Duration) {
+        self.insert_duration[table as usize].observe(duration);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        render_single(&mut out, "orders_received_total", "counter",
+            "Total number of orders received over HTTP.", &self.orders_received);
+        render_single(&mut out, "orders_flushed_total", "counter",
+            "Total number of orders persisted to the database.", &self.orders_flushed);
+        render_single(&mut out, "orders_queue_depth", "gauge",
+            "Current number of orders in the in-memory queue.", &self.queue_depth);
+        render_header(&mut out, "orders_flush_duration_seconds", "histogram",
+            "Time taken to flush the in-memory queue to the database.");
+        self.flush_duration.render(&mut out, "orders_flush_duration_seconds", "");
+        render_header(&mut out, "orders_insert_duration_seconds", "histogram",
+            "Time taken to insert the rows of a saved order, by table.");
+        for table in Table::ALL {
+            let labels = format!("table=\"{}\"", table.name());
+            self.insert_duration[table as usize].render(&mut out, "orders_insert_duration_seconds", &labels);
+        }
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            orders_received: AtomicU64::new(0),
+            orders_flushed: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+            last_flush_at: AtomicI64::new(0),
+            flush_duration: Histogram::new(FLUSH_DURATION_BUCKETS),
+            insert_duration: Table::ALL.map(|_| Histogram::new(INSERT_DURATION_BUCKETS)),
+        }
+    }
+}
+
+impl Histogram {
+    /// Creates an empty histogram with buckets bounded by `bounds`.
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            bucket_counts: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a single observation.
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = self.bounds.iter().position(|&bound| seconds <= bound) {
+            self.bucket_counts[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Appends the samples of the histogram to `out` in the Prometheus text exposition format.
+    /// `labels` (e.g. `table="orders"`, or empty) are added to every sample; the `# HELP` and
+    /// `# TYPE` lines are written by `render_header`.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        // The `_bucket` samples carry the `le` label next to the given ones.
+        let bucket_labels = |le: &str| {
+            if labels.is_empty() { format!("le=\"{le}\"") } else { format!("{labels},le=\"{le}\"") }
+        };
+
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.bucket_counts) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{{}}} {cumulative}", bucket_labels(&bound.to_string()));
+        }
+
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{labels}}}") };
+        let _ = writeln!(out, "{name}_bucket{{{}}} {count}", bucket_labels("+Inf"));
+        let _ = writeln!(out, "{name}_sum{labels} {sum}");
+        let _ = writeln!(out, "{name}_count{labels} {count}");
+    }
+}
+
+/// Appends the `# HELP` and `# TYPE` lines of a metric to `out`.
+fn render_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Appends a single-valued metric (a counter or a gauge) to `out` in the Prometheus text exposition format.
+fn render_single(out: &mut String, name: &str, kind: &str, help: &str, value: &AtomicU64) {
+    render_header(out, name, kind, help);
+    let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+}
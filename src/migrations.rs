@@ -0,0 +1,37 @@
+use tokio_postgres::{Client as PostgresClient, error::Error as PostgresError};
+
+/// The database schema: the `orders` table and the `deliveries`, `payments`, and `items`
+/// tables referencing it. Every statement is idempotent (`CREATE ... IF NOT EXISTS`).
+const SCHEMA: &str = include_str!("resources/db/schema.sql");
+
+/// Provisions the database schema, creating the PostgreSQL schema `schema` and the tables
+/// that don't exist yet. Existing tables are left untouched, so it is safe to run on every startup.
+///
+/// The tables are created in `schema` because the connections use it as their `search_path`
+/// (see `AppState::new`).
+///
+/// # Parameters
+/// - `client`: A reference to the `PostgresClient` used for database operations.
+/// - `schema`: The PostgreSQL schema holding the tables, as accepted by `parse_schema_name`.
+///
+/// # Returns
+/// `Ok(())` on success, or a `PostgresError` if a statement fails.
+pub async fn run(client: &PostgresClient, schema: &str) -> Result<(), PostgresError> {
+    client.batch_execute(&format!("CREATE SCHEMA IF NOT EXISTS {schema}")).await?;
+    client.batch_execute(SCHEMA).await
+}
+
+/// Parses the `--schema` argument, accepting only plain SQL identifiers (letters, digits, and
+/// underscores, not starting with a digit), so that the name can be put into SQL and the
+/// connection options without quoting.
+pub fn parse_schema_name(schema: &str) -> Result<String, String> {
+    let mut chars = schema.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if valid {
+        Ok(schema.to_string())
+    } else {
+        Err("expected letters, digits, and underscores, not starting with a digit".to_string())
+    }
+}
@@ -1,10 +1,13 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
 
 /// Represents the delivery details for an order.
 ///
 /// This structure contains information related to the recipient's delivery address, 
 /// contact information, and location details (such as the city and region).
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Delivery {
     /// Name of the recipient.
     pub name: String,
@@ -26,7 +29,9 @@ pub struct Delivery {
 ///
 /// This structure contains all information related to the payment for an order, 
 /// including transaction ID, amount, payment date, and currency.
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// Monetary amounts are integers in the smallest currency unit.
+/// TODO: fractional amounts need a decimal type here and NUMERIC columns in `schema.sql`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Payment {
     /// Unique transaction identifier.
     pub transaction: String,
@@ -37,41 +42,52 @@ pub struct Payment {
     /// Payment provider (e.g., Visa, MasterCard, PayPal).
     pub provider: String,
     /// Total amount paid for the order.
-    pub amount: i32,
+    pub amount: i64,
     /// Date and time of the payment (in Unix timestamp format).
     pub payment_dt: i64,
     /// Bank through which the payment was processed.
     pub bank: String,
     /// Cost of delivery for the order.
-    pub delivery_cost: i32,
+    pub delivery_cost: i64,
     /// Total cost of the goods in the order.
-    pub goods_total: i32,
+    pub goods_total: i64,
     /// Custom fee applied to the order, if applicable.
     pub custom_fee: i64,
 }
 
+/// A stored payment together with the order it belongs to, as looked up by transaction ID
+/// for reconciliation.
+#[derive(Serialize, Debug, Clone)]
+pub struct PaymentRecord {
+    /// Unique identifier of the order the payment belongs to.
+    pub order_uid: String,
+    /// The payment details.
+    pub payment: Payment,
+}
+
 /// Represents an item in an order.
 ///
 /// This structure contains details for individual items included in an order, such as
 /// the item's ID, price, and other related information.
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Item {
     /// Unique identifier for the item (e.g., product code).
     pub chrt_id: i64,
     /// Tracking number for the item shipment.
     pub track_number: String,
     /// Price of the item.
-    pub price: i32,
+    pub price: i64,
     /// RID (Retailer Identifier) for the item.
     pub rid: String,
     /// Name or description of the item.
     pub name: String,
-    /// Discount or sale amount applied to the item.
-    pub sale: i32,
+    /// Discount applied to the item, in percent.
+    pub sale: i64,
     /// Size of the item (e.g., S, M, L).
     pub size: String,
-    /// Total price for the item after applying discounts.
-    pub total_price: i32,
+    /// Total price for the item after applying the discount: `price * (100 - sale) / 100`,
+    /// rounded either way.
+    pub total_price: i64,
     /// Unique NM (nomenclature) ID for the item.
     pub nm_id: i64,
     /// Brand of the item.
@@ -84,7 +100,7 @@ pub struct Item {
 ///
 /// The `Order` structure contains the full order information such as unique identifiers,
 /// delivery and payment data, the list of items in the order, and other metadata.
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Order {
     /// Unique identifier for the order.
     pub order_uid: String,
@@ -109,9 +125,581 @@ pub struct Order {
     /// Shard key used for distributed storage.
     pub shardkey: String,
     /// SM (Sales Manager) identifier associated with the order.
-    pub sm_id: i32,
+    pub sm_id: i64,
     /// Date and time when the order was created.
     pub date_created: String,
     /// Out of order shard key.
     pub oof_shard: String,
+    /// When the service received the order. Set by the server when the order is queued and
+    /// ignored in request bodies; `None` for orders stored before it was recorded.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<DateTime<Utc>>,
+}
+
+/// The placeholder that replaces the personal data of an anonymized order, see `Order::anonymize`.
+pub const ANONYMIZED: &str = "anonymized";
+
+/// The placeholder that replaces the delivery email of an anonymized order; the `.invalid`
+/// top-level domain is reserved, so nothing can ever be delivered to it.
+pub const ANONYMIZED_EMAIL: &str = "anonymized@anonymized.invalid";
+
+/// Configurable rules applied by `Order::validate` on top of the fixed ones.
+/// - `strict_delivery`: Whether the delivery phone and zip code must match their formats exactly.
+/// - `allowed_locales`: The accepted values of `locale`, or `None` to accept any.
+/// - `allowed_entries`: The accepted values of `entry`, or `None` to accept any.
+/// - `max_items`: The maximum number of items in an order; `0` accepts any number.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationRules {
+    pub strict_delivery: bool,
+    pub allowed_locales: Option<Vec<String>>,
+    pub allowed_entries: Option<Vec<String>>,
+    pub max_items: usize,
+}
+
+/// The differences between two versions of an order, as returned by `Order::diff`.
+/// Every list is empty if the respective part didn't change.
+/// - `fields`: The changed fields of the order itself, e.g. `track_number`.
+/// - `delivery`: The changed fields of the delivery.
+/// - `payment`: The changed fields of the payment other than the amounts.
+/// - `amounts`: The changed amounts of the payment, with the difference.
+/// - `added_items`: The items of the new version without a counterpart in the old one.
+/// - `removed_items`: The items of the old version without a counterpart in the new one.
+/// - `changed_items`: The items present in both versions with changed fields.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct OrderDiff {
+    pub fields: Vec<FieldChange>,
+    pub delivery: Vec<FieldChange>,
+    pub payment: Vec<FieldChange>,
+    pub amounts: Vec<AmountChange>,
+    pub added_items: Vec<Item>,
+    pub removed_items: Vec<Item>,
+    pub changed_items: Vec<ItemChange>,
+}
+
+/// A field that differs between two versions of an order.
+/// - `field`: The name of the field, as in the JSON representation.
+/// - `old`, `new`: The values of the field in the old and the new version.
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// A monetary amount of the payment that differs between two versions of an order.
+/// - `field`: The name of the field, e.g. `amount` or `delivery_cost`.
+/// - `old`, `new`: The amounts in the old and the new version.
+/// - `delta`: `new - old`.
+#[derive(Serialize, Debug, Clone)]
+pub struct AmountChange {
+    pub field: &'static str,
+    pub old: i64,
+    pub new: i64,
+    pub delta: i64,
+}
+
+/// An item present in both versions of an order with changed fields.
+/// - `chrt_id`: The identifier the item was matched by.
+/// - `changes`: The changed fields of the item.
+#[derive(Serialize, Debug, Clone)]
+pub struct ItemChange {
+    pub chrt_id: i64,
+    pub changes: Vec<FieldChange>,
+}
+
+impl OrderDiff {
+    /// Returns whether the two versions are the same.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.delivery.is_empty()
+            && self.payment.is_empty()
+            && self.amounts.is_empty()
+            && self.added_items.is_empty()
+            && self.removed_items.is_empty()
+            && self.changed_items.is_empty()
+    }
+}
+
+impl Order {
+    /// Compares the order with `other`, a newer version of it, field by field.
+    ///
+    /// Items are matched by `chrt_id`; if several items share one, they are matched in the order
+    /// they are listed. `order_uid` and `received_at` are not compared, since they identify the
+    /// order rather than describe it.
+    ///
+    /// # Returns
+    /// The differences, see `OrderDiff`; `OrderDiff::is_empty` if there are none.
+    pub fn diff(&self, other: &Order) -> OrderDiff {
+        let mut diff = OrderDiff::default();
+
+        let fields = &mut diff.fields;
+        compare(fields, "track_number", &self.track_number, &other.track_number);
+        compare(fields, "entry", &self.entry, &other.entry);
+        compare(fields, "locale", &self.locale, &other.locale);
+        compare(fields, "internal_signature", &self.internal_signature, &other.internal_signature);
+        compare(fields, "customer_id", &self.customer_id, &other.customer_id);
+        compare(fields, "delivery_service", &self.delivery_service, &other.delivery_service);
+        compare(fields, "shardkey", &self.shardkey, &other.shardkey);
+        compare(fields, "sm_id", &self.sm_id, &other.sm_id);
+        compare(fields, "date_created", &self.date_created, &other.date_created);
+        compare(fields, "oof_shard", &self.oof_shard, &other.oof_shard);
+
+        let (old, new) = (&self.delivery, &other.delivery);
+        let delivery = &mut diff.delivery;
+        compare(delivery, "name", &old.name, &new.name);
+        compare(delivery, "phone", &old.phone, &new.phone);
+        compare(delivery, "zip", &old.zip, &new.zip);
+        compare(delivery, "city", &old.city, &new.city);
+        compare(delivery, "address", &old.address, &new.address);
+        compare(delivery, "region", &old.region, &new.region);
+        compare(delivery, "email", &old.email, &new.email);
+
+        let (old, new) = (&self.payment, &other.payment);
+        let payment = &mut diff.payment;
+        compare(payment, "transaction", &old.transaction, &new.transaction);
+        compare(payment, "request_id", &old.request_id, &new.request_id);
+        compare(payment, "currency", &old.currency, &new.currency);
+        compare(payment, "provider", &old.provider, &new.provider);
+        compare(payment, "payment_dt", &old.payment_dt, &new.payment_dt);
+        compare(payment, "bank", &old.bank, &new.bank);
+        for (field, old, new) in [
+            ("amount", old.amount, new.amount),
+            ("delivery_cost", old.delivery_cost, new.delivery_cost),
+            ("goods_total", old.goods_total, new.goods_total),
+            ("custom_fee", old.custom_fee, new.custom_fee),
+        ] {
+            if old != new {
+                diff.amounts.push(AmountChange { field, old, new, delta: new.saturating_sub(old) });
+            }
+        }
+
+        let mut unmatched: Vec<&Item> = other.items.iter().collect();
+        for old in &self.items {
+            let Some(position) = unmatched.iter().position(|new| new.chrt_id == old.chrt_id) else {
+                diff.removed_items.push(old.clone());
+                continue;
+            };
+            let new = unmatched.remove(position);
+
+            let mut changes = Vec::new();
+            compare(&mut changes, "track_number", &old.track_number, &new.track_number);
+            compare(&mut changes, "price", &old.price, &new.price);
+            compare(&mut changes, "rid", &old.rid, &new.rid);
+            compare(&mut changes, "name", &old.name, &new.name);
+            compare(&mut changes, "sale", &old.sale, &new.sale);
+            compare(&mut changes, "size", &old.size, &new.size);
+            compare(&mut changes, "total_price", &old.total_price, &new.total_price);
+            compare(&mut changes, "nm_id", &old.nm_id, &new.nm_id);
+            compare(&mut changes, "brand", &old.brand, &new.brand);
+            compare(&mut changes, "status", &old.status, &new.status);
+            if !changes.is_empty() {
+                diff.changed_items.push(ItemChange { chrt_id: old.chrt_id, changes });
+            }
+        }
+        diff.added_items = unmatched.into_iter().cloned().collect();
+
+        diff
+    }
+
+    /// Cleans up the submitted text so that the same data doesn't end up stored in different
+    /// spellings: trims leading and trailing whitespace of every string field of the order, its
+    /// delivery, its payment, and its items, and lowercases `delivery.email`. Numbers and
+    /// `received_at` are left as they are.
+    pub fn normalize(&mut self) {
+        for field in [
+            &mut self.order_uid, &mut self.track_number, &mut self.entry, &mut self.locale,
+            &mut self.internal_signature, &mut self.customer_id, &mut self.delivery_service,
+            &mut self.shardkey, &mut self.date_created, &mut self.oof_shard,
+        ] {
+            trim(field);
+        }
+
+        let delivery = &mut self.delivery;
+        for field in [
+            &mut delivery.name, &mut delivery.phone, &mut delivery.zip, &mut delivery.city,
+            &mut delivery.address, &mut delivery.region, &mut delivery.email,
+        ] {
+            trim(field);
+        }
+        delivery.email.make_ascii_lowercase();
+
+        let payment = &mut self.payment;
+        for field in [&mut payment.transaction, &mut payment.request_id, &mut payment.currency, &mut payment.provider, &mut payment.bank] {
+            trim(field);
+        }
+
+        for item in &mut self.items {
+            for field in [&mut item.track_number, &mut item.rid, &mut item.name, &mut item.size, &mut item.brand] {
+                trim(field);
+            }
+        }
+    }
+
+    /// Scrubs the personal data of the order, e.g. on a GDPR request, while keeping the record for
+    /// accounting: the recipient's name, phone, address, and email and the `customer_id` are
+    /// replaced with placeholders. Items, amounts, and everything else are left as they are.
+    pub fn anonymize(&mut self) {
+        for field in [&mut self.delivery.name, &mut self.delivery.phone, &mut self.delivery.address, &mut self.customer_id] {
+            *field = ANONYMIZED.to_string();
+        }
+        self.delivery.email = ANONYMIZED_EMAIL.to_string();
+    }
+
+    /// Checks that the order is consistent enough to be accepted.
+    ///
+    /// The following rules are checked:
+    /// - `order_uid` is not empty;
+    /// - the order has at least one item and no more than `rules.max_items`, if set. An order with
+    ///   too many items is rejected without checking the remaining rules, so that a huge order
+    ///   doesn't produce a violation per item;
+    /// - monetary fields of the payment and the items are non-negative;
+    /// - every item's `sale` is a percentage and its `total_price` is `price` with the sale applied
+    ///   (see `Item::total_price`), and `payment.goods_total` is the sum of the item totals;
+    /// - the delivery email looks like an email address;
+    /// - the delivery phone is an E.164 phone number and the zip code consists of digits.
+    ///   With `rules.strict_delivery` unset, spaces, dashes, and parentheses are allowed in the phone,
+    ///   the leading `+` may be omitted, and the zip code may contain spaces and dashes;
+    /// - `locale` and `entry` are among the allowed values, if `rules` restricts them.
+    ///
+    /// # Parameters
+    /// - `rules`: The configurable rules, see `ValidationRules`.
+    ///
+    /// # Returns
+    /// `Ok(())` if the order is valid, or every violated rule as a human-readable message.
+    pub fn validate(&self, rules: &ValidationRules) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+
+        if self.order_uid.trim().is_empty() {
+            violations.push("order_uid must not be empty".to_string());
+        }
+
+        if self.items.is_empty() {
+            violations.push("items must not be empty".to_string());
+        }
+
+        if rules.max_items > 0 && self.items.len() > rules.max_items {
+            violations.push(format!("items must not contain more than {} items, got {}", rules.max_items, self.items.len()));
+            return Err(violations);
+        }
+
+        let payment_amounts = [
+            ("payment.amount", self.payment.amount),
+            ("payment.delivery_cost", self.payment.delivery_cost),
+            ("payment.goods_total", self.payment.goods_total),
+            ("payment.custom_fee", self.payment.custom_fee),
+        ];
+        for (field, value) in payment_amounts {
+            if value < 0 {
+                violations.push(format!("{field} must not be negative, got {value}"));
+            }
+        }
+
+        for (i, item) in self.items.iter().enumerate() {
+            let item_amounts = [
+                ("price", item.price),
+                ("sale", item.sale),
+                ("total_price", item.total_price),
+            ];
+            for (field, value) in item_amounts {
+                if value < 0 {
+                    violations.push(format!("items[{i}].{field} must not be negative, got {value}"));
+                }
+            }
+
+            if item.sale > 100 {
+                violations.push(format!("items[{i}].sale must be a percentage up to 100, got {}", item.sale));
+            } else if item.price >= 0 && item.sale >= 0 {
+                // 100 * total_price must be within 100 of price * (100 - sale), i.e. either rounding of the exact total.
+                let discounted = i128::from(item.price) * i128::from(100 - item.sale);
+                if (i128::from(item.total_price) * 100 - discounted).abs() >= 100 {
+                    violations.push(format!(
+                        "items[{i}].total_price is {}, expected {} (price {} with a {}% sale)",
+                        item.total_price, discounted / 100, item.price, item.sale,
+                    ));
+                }
+            }
+        }
+
+        let items_total: i128 = self.items.iter().map(|item| i128::from(item.total_price)).sum();
+        if i128::from(self.payment.goods_total) != items_total {
+            violations.push(format!(
+                "payment.goods_total is {}, expected {} (the sum of the item totals)",
+                self.payment.goods_total, items_total,
+            ));
+        }
+
+        if !is_valid_email(&self.delivery.email) {
+            violations.push(format!("delivery.email is not a valid email address: {:?}", self.delivery.email));
+        }
+
+        if !is_valid_phone(&self.delivery.phone, rules.strict_delivery) {
+            violations.push(format!(
+                "delivery.phone is not a valid E.164 phone number (e.g. +79991234567): {:?}", self.delivery.phone
+            ));
+        }
+
+        if !is_valid_zip(&self.delivery.zip, rules.strict_delivery) {
+            violations.push(format!("delivery.zip must consist of digits: {:?}", self.delivery.zip));
+        }
+
+        let restricted = [
+            ("locale", &self.locale, &rules.allowed_locales),
+            ("entry", &self.entry, &rules.allowed_entries),
+        ];
+        for (field, value, allowed) in restricted {
+            if let Some(allowed) = allowed {
+                if !allowed.contains(value) {
+                    violations.push(format!("{field} must be one of {}, got {value:?}", allowed.join(", ")));
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// Removes leading and trailing whitespace from `value`, reallocating only if there is any.
+fn trim(value: &mut String) {
+    let trimmed = value.trim();
+    if trimmed.len() != value.len() {
+        *value = trimmed.to_string();
+    }
+}
+
+/// Performs a basic sanity check of an email address: a non-empty local part, a single `@`,
+/// and a domain with a dot that neither starts nor ends it. No whitespace is allowed.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.chars().any(char::is_whitespace)
+}
+
+/// Records a `FieldChange` of `field` in `changes` if `old` and `new` differ.
+fn compare<T: PartialEq + Serialize>(changes: &mut Vec<FieldChange>, field: &'static str, old: &T, new: &T) {
+    if old != new {
+        changes.push(FieldChange { field, old: json!(old), new: json!(new) });
+    }
+}
+
+/// Checks that `phone` is an E.164 number: a `+`, a non-zero digit, and up to 14 more digits.
+/// Unless `strict`, the `+` is optional and spaces, dashes, and parentheses are ignored.
+fn is_valid_phone(phone: &str, strict: bool) -> bool {
+    let phone: String = if strict {
+        phone.to_string()
+    } else {
+        phone.chars().filter(|c| !matches!(c, ' ' | '-' | '(' | ')')).collect()
+    };
+    let digits = match phone.strip_prefix('+') {
+        Some(digits) => digits,
+        None if !strict => phone.as_str(),
+        None => return false,
+    };
+
+    (2..=15).contains(&digits.len())
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && !digits.starts_with('0')
+}
+
+/// Checks that `zip` is a non-empty string of digits. Unless `strict`, spaces and dashes
+/// between the digits are allowed as well.
+fn is_valid_zip(zip: &str, strict: bool) -> bool {
+    let mut digits = 0;
+    for c in zip.chars() {
+        match c {
+            '0'..='9' => digits += 1,
+            ' ' | '-' if !strict => {}
+            _ => return false,
+        }
+    }
+
+    digits > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the valid sample order of the integration tests.
+    fn sample_order() -> Order {
+        serde_json::from_str(include_str!("../tests/fixtures/order.json")).unwrap()
+    }
+
+    #[test]
+    fn integer_amounts_deserialize() {
+        let order = sample_order();
+
+        assert_eq!(order.payment.amount, 1817);
+        assert_eq!(order.payment.delivery_cost, 1500);
+        assert_eq!(order.payment.goods_total, 317);
+        assert_eq!(order.items[0].price, 453);
+        assert_eq!(order.items[0].total_price, 317);
+    }
+
+    #[test]
+    fn amounts_beyond_32_bits_round_trip() {
+        let payment = json!({
+            "transaction": "t", "request_id": "", "currency": "USD", "provider": "wbpay",
+            "amount": 1_234_567_890_123_i64, "payment_dt": 1637907727, "bank": "alpha",
+            "delivery_cost": 1_234_567_890_123_i64, "goods_total": 1_234_567_890_123_i64, "custom_fee": 0,
+        });
+        let item = json!({
+            "chrt_id": 1, "track_number": "T", "price": 1_234_567_890_123_i64, "rid": "r", "name": "n",
+            "sale": 0, "size": "0", "total_price": 1_234_567_890_123_i64, "nm_id": 1, "brand": "b", "status": 202,
+        });
+
+        let parsed: Payment = serde_json::from_value(payment.clone()).unwrap();
+        assert_eq!(parsed.amount, 1_234_567_890_123);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), payment);
+
+        let parsed: Item = serde_json::from_value(item.clone()).unwrap();
+        assert_eq!(parsed.price, 1_234_567_890_123);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), item);
+    }
+
+    /// Returns the rule violations of `order`, which must be invalid.
+    fn violations(order: &Order, rules: &ValidationRules) -> Vec<String> {
+        order.validate(rules).expect_err("the order should be invalid")
+    }
+
+    #[test]
+    fn sample_order_is_valid() {
+        assert_eq!(sample_order().validate(&ValidationRules::default()), Ok(()));
+    }
+
+    #[test]
+    fn empty_order_uid_is_rejected() {
+        let mut order = sample_order();
+        order.order_uid = "  ".to_string();
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["order_uid must not be empty"]);
+    }
+
+    #[test]
+    fn empty_items_are_rejected() {
+        let mut order = sample_order();
+        order.items.clear();
+        order.payment.goods_total = 0;
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["items must not be empty"]);
+    }
+
+    #[test]
+    fn too_many_items_are_rejected() {
+        let mut order = sample_order();
+        order.items.push(order.items[0].clone());
+        let rules = ValidationRules { max_items: 1, ..Default::default() };
+
+        assert_eq!(violations(&order, &rules), ["items must not contain more than 1 items, got 2"]);
+    }
+
+    #[test]
+    fn negative_amount_is_rejected() {
+        let mut order = sample_order();
+        order.payment.amount = -1;
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["payment.amount must not be negative, got -1"]);
+    }
+
+    #[test]
+    fn negative_custom_fee_is_rejected() {
+        let mut order = sample_order();
+        order.payment.custom_fee = -1;
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["payment.custom_fee must not be negative, got -1"]);
+    }
+
+    #[test]
+    fn negative_item_price_is_rejected() {
+        let mut order = sample_order();
+        order.items[0].price = -453;
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["items[0].price must not be negative, got -453"]);
+    }
+
+    #[test]
+    fn sale_above_100_percent_is_rejected() {
+        let mut order = sample_order();
+        order.items[0].sale = 101;
+
+        assert_eq!(violations(&order, &ValidationRules::default()), ["items[0].sale must be a percentage up to 100, got 101"]);
+    }
+
+    #[test]
+    fn total_price_not_matching_the_sale_is_rejected() {
+        let mut order = sample_order();
+        order.items[0].total_price = 300;
+        order.payment.goods_total = 300;
+
+        assert_eq!(
+            violations(&order, &ValidationRules::default()),
+            ["items[0].total_price is 300, expected 317 (price 453 with a 30% sale)"],
+        );
+    }
+
+    #[test]
+    fn goods_total_not_matching_the_items_is_rejected() {
+        let mut order = sample_order();
+        order.payment.goods_total = 318;
+
+        assert_eq!(
+            violations(&order, &ValidationRules::default()),
+            ["payment.goods_total is 318, expected 317 (the sum of the item totals)"],
+        );
+    }
+
+    #[test]
+    fn invalid_email_is_rejected() {
+        let mut order = sample_order();
+        order.delivery.email = "test@gmail".to_string();
+
+        assert_eq!(
+            violations(&order, &ValidationRules::default()),
+            ["delivery.email is not a valid email address: \"test@gmail\""],
+        );
+    }
+
+    #[test]
+    fn invalid_phone_is_rejected() {
+        let mut order = sample_order();
+        order.delivery.phone = "+972 000 CALL".to_string();
+
+        assert_eq!(
+            violations(&order, &ValidationRules::default()),
+            ["delivery.phone is not a valid E.164 phone number (e.g. +79991234567): \"+972 000 CALL\""],
+        );
+    }
+
+    #[test]
+    fn invalid_zip_is_rejected() {
+        let mut order = sample_order();
+        order.delivery.zip = "2639-809".to_string();
+        let rules = ValidationRules { strict_delivery: true, ..Default::default() };
+
+        assert_eq!(violations(&order, &rules), ["delivery.zip must consist of digits: \"2639-809\""]);
+    }
+
+    #[test]
+    fn locale_not_allowed_is_rejected() {
+        let rules = ValidationRules { allowed_locales: Some(vec!["ru".to_string()]), ..Default::default() };
+
+        assert_eq!(violations(&sample_order(), &rules), ["locale must be one of ru, got \"en\""]);
+    }
+
+    #[test]
+    fn entry_not_allowed_is_rejected() {
+        let rules = ValidationRules { allowed_entries: Some(vec!["WBRU".to_string(), "WBKZ".to_string()]), ..Default::default() };
+
+        assert_eq!(violations(&sample_order(), &rules), ["entry must be one of WBRU, WBKZ, got \"WBIL\""]);
+    }
 }
@@ -0,0 +1,192 @@
+use tokio_postgres::{Client as PostgresClient, Config as PostgresConfig, error::Error as PostgresError, NoTls, Statement};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use log::{debug, warn, error as cry};
+
+/// A fixed-size pool of PostgreSQL connections.
+///
+/// Connections are opened lazily and reused between checkouts. At most `size` connections
+/// are checked out at the same time; further callers wait until one is returned.
+/// - `config`: The `tokio_postgres` connection settings used to open new connections.
+/// - `idle`: Connections that are currently not in use.
+/// - `permits`: Limits the number of simultaneously checked out connections.
+pub struct Pool {
+    config: PostgresConfig,
+    idle: Mutex<Vec<Connection>>,
+    permits: Semaphore,
+}
+
+/// An open connection together with the statements prepared on it.
+/// - `client`: The database client.
+/// - `statements`: Prepared statements keyed by their query text (see `PooledClient::prepare_cached`).
+struct Connection {
+    client: PostgresClient,
+    statements: HashMap<&'static str, Statement>,
+}
+
+/// A connection checked out from the `Pool`. It dereferences to `PostgresClient` and
+/// returns to the pool when dropped.
+pub struct PooledClient<'a> {
+    connection: Option<Connection>,
+    pool: &'a Pool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Pool {
+    /// Creates a new pool and opens its first connection, so that invalid connection
+    /// parameters are reported right away.
+    ///
+    /// # Parameters
+    /// - `config`: The `tokio_postgres` connection settings.
+    /// - `size`: Maximum number of simultaneously open connections.
+    ///
+    /// # Returns
+    /// The pool, or a `PostgresError` if the first connection can't be established.
+    pub async fn new(config: PostgresConfig, size: usize) -> Result<Self, PostgresError> {
+        let pool = Pool {
+            config,
+            idle: Mutex::new(Vec::with_capacity(size)),
+            permits: Semaphore::new(size),
+        };
+
+        let connection = pool.connect().await?;
+        pool.idle.lock().unwrap().push(connection);
+
+        Ok(pool)
+    }
+
+    /// Checks out a connection, waiting if all of them are in use. Connections that were
+    /// closed (e.g. because the database went away) are discarded and replaced with new ones.
+    ///
+    /// # Returns
+    /// A `PooledClient`, or a `PostgresError` if a new connection can't be established.
+    pub async fn get(&self) -> Result<PooledClient<'_>, PostgresError> {
+        let permit = self.permits.acquire().await.expect("Pool semaphore is never closed");
+
+        let idle = {
+            let mut idle = self.idle.lock().unwrap();
+            let before = idle.len();
+            idle.retain(|connection| !connection.client.is_closed());
+            if idle.len() < before {
+                warn!("Discarded {} closed database connections", before - idle.len());
+            }
+            idle.pop()
+        };
+
+        // When the database comes back after an outage, this is where the connection is rebuilt.
+        let connection = match idle {
+            Some(connection) => connection,
+            None => self.connect().await?,
+        };
+
+        Ok(PooledClient {
+            connection: Some(connection),
+            pool: self,
+            _permit: permit,
+        })
+    }
+
+    /// Opens a new connection and spawns a task that drives it.
+    async fn connect(&self) -> Result<Connection, PostgresError> {
+        let (client, connection) = self.config.connect(NoTls).await?;
+
+        // Spawn a task to handle the database connection.
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                cry!("Connection error: {}", e);
+            }
+        });
+
+        debug!("Opened a new database connection");
+        Ok(Connection {
+            client,
+            statements: HashMap::new(),
+        })
+    }
+}
+
+impl PooledClient<'_> {
+    /// Prepares `query` on this connection, or returns the statement prepared by an earlier call.
+    /// Statements are kept for the lifetime of the connection, so the server parses and plans
+    /// a frequently used query once per connection instead of once per execution.
+    ///
+    /// # Parameters
+    /// - `query`: The SQL text of the statement.
+    ///
+    /// # Returns
+    /// The prepared `Statement`, or a `PostgresError` if it can't be prepared.
+    pub async fn prepare_cached(&mut self, query: &'static str) -> Result<Statement, PostgresError> {
+        let connection = self.connection.as_mut().expect("Connection is only taken on drop");
+
+        if let Some(statement) = connection.statements.get(query) {
+            return Ok(statement.clone());
+        }
+
+        let statement = connection.client.prepare(query).await?;
+        connection.statements.insert(query, statement.clone());
+        Ok(statement)
+    }
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = PostgresClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.connection.as_ref().expect("Connection is only taken on drop").client
+    }
+}
+
+impl DerefMut for PooledClient<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.connection.as_mut().expect("Connection is only taken on drop").client
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            if connection.client.is_closed() {
+                warn!("Database connection was closed, a new one will be opened on the next checkout");
+            } else {
+                self.pool.idle.lock().unwrap().push(connection);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    /// Returns the connection settings from `DATABASE_URL`.
+    fn database_config() -> PostgresConfig {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a PostgreSQL database");
+        PostgresConfig::from_str(&url).unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in DATABASE_URL"]
+    async fn terminated_connection_is_replaced() {
+        let pool = Pool::new(database_config(), 1).await.unwrap();
+        let pid: i32 = pool.get().await.unwrap().query_one("SELECT pg_backend_pid()", &[]).await.unwrap().get(0);
+
+        // Drop the idle connection from the server side, as a database restart would.
+        let (admin, connection) = database_config().connect(NoTls).await.unwrap();
+        tokio::spawn(connection);
+        admin.execute("SELECT pg_terminate_backend($1)", &[&pid]).await.unwrap();
+        for _ in 0..100 {
+            if pool.idle.lock().unwrap().iter().all(|connection| connection.client.is_closed()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let client = pool.get().await.unwrap();
+        let new_pid: i32 = client.query_one("SELECT pg_backend_pid()", &[]).await.unwrap().get(0);
+        assert_ne!(new_pid, pid);
+    }
+}
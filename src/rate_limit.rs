@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a client stays idle before its bucket is forgotten.
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(60);
+
+/// Number of tracked clients above which idle buckets are removed.
+const CLEANUP_THRESHOLD: usize = 10_000;
+
+/// A per-IP token bucket rate limiter.
+///
+/// Every client may make up to `rate_per_sec` requests in a burst; the bucket refills
+/// continuously at `rate_per_sec` tokens per second.
+/// - `rate_per_sec`: Refill rate and capacity of every bucket.
+/// - `buckets`: The bucket of every client seen recently, keyed by its IP address.
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+/// The state of a single client's bucket.
+/// - `tokens`: Number of requests the client may still make right away.
+/// - `updated_at`: When `tokens` was last refilled.
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate_per_sec` requests per second from every IP address.
+    ///
+    /// # Panics
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new(rate_per_sec: u32) -> Self {
+        assert!(rate_per_sec > 0, "Rate limit must be positive");
+
+        RateLimiter {
+            rate_per_sec: rate_per_sec as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of `ip`.
+    ///
+    /// # Returns
+    /// `Ok(())` if the request is allowed, or `Err` with the time until the next token is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > CLEANUP_THRESHOLD {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < IDLE_BUCKET_TTL);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: self.rate_per_sec, updated_at: now });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate_per_sec).min(self.rate_per_sec);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate_per_sec))
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::order::Order;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// A bounded cache of stored orders keyed by `order_uid`, so that repeated lookups of the same
+/// order through `GET /order/:order_uid` don't hit the database every time. Once full, the least
+/// recently used order is evicted.
+///
+/// An order loaded while the cache is being invalidated may already be outdated, so loads are
+/// tagged with the `generation` they started in and only cached if no invalidation happened since.
+/// - `capacity`: Maximum number of cached orders; `0` disables the cache.
+/// - `inner`: The cached orders and their recency.
+pub struct ReadCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+/// The state of a `ReadCache`.
+/// - `orders`: The cached orders with the tick they were last used at.
+/// - `recency`: The `order_uid`s by the tick they were last used at, least recent first.
+/// - `tick`: Increases with every use of an order.
+/// - `generation`: Increases with every invalidation.
+#[derive(Default)]
+struct Inner {
+    orders: HashMap<String, (Order, u64)>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    generation: u64,
+}
+
+impl ReadCache {
+    /// Creates an empty cache holding up to `capacity` orders; `0` disables it.
+    pub fn new(capacity: usize) -> Self {
+        ReadCache {
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Returns whether the cache holds any orders at all, i.e. its capacity is not zero.
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns the cached order with `order_uid`, marking it as the most recently used one.
+    pub fn get(&self, order_uid: &str) -> Option<Order> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        let (order, last_used) = inner.orders.get_mut(order_uid)?;
+        inner.tick += 1;
+        inner.recency.remove(last_used);
+        inner.recency.insert(inner.tick, order_uid.to_string());
+        *last_used = inner.tick;
+        Some(order.clone())
+    }
+
+    /// Returns the current generation, to be passed to `insert` along with the order loaded afterwards.
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Caches `order`, loaded after `generation` was taken, evicting the least recently used order
+    /// if the cache is full. Does nothing if the cache was invalidated in the meantime.
+    pub fn insert(&self, order: Order, generation: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        if inner.generation != generation {
+            return;
+        }
+
+        inner.tick += 1;
+        inner.recency.insert(inner.tick, order.order_uid.clone());
+        if let Some((_, last_used)) = inner.orders.insert(order.order_uid.clone(), (order, inner.tick)) {
+            inner.recency.remove(&last_used);
+        }
+
+        if inner.orders.len() > self.capacity {
+            if let Some((_, evicted)) = inner.recency.pop_first() {
+                inner.orders.remove(&evicted);
+            }
+        }
+    }
+
+    /// Removes the order with `order_uid`, e.g. because it was overwritten, and discards the loads
+    /// that are still in progress.
+    pub fn invalidate(&self, order_uid: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        inner.generation += 1;
+        if let Some((_, last_used)) = inner.orders.remove(order_uid) {
+            inner.recency.remove(&last_used);
+        }
+    }
+}
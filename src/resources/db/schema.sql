@@ -12,6 +12,17 @@ CREATE TABLE IF NOT EXISTS orders(
    oof_shard            VARCHAR
 );
 
+-- When the service received the order, independent of the client-supplied `date_created`.
+-- Orders stored before the column existed keep NULL, as their ingestion time is unknown.
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS received_at TIMESTAMPTZ;
+ALTER TABLE orders ALTER COLUMN received_at SET DEFAULT now();
+
+-- Time-windowed pulls (GET /orders?from=&to=).
+CREATE INDEX IF NOT EXISTS orders_received_at_idx ON orders (received_at);
+
+-- Customer order history lookups (GET /orders/by-customer/{customer_id}).
+CREATE INDEX IF NOT EXISTS orders_customer_id_idx ON orders (customer_id, date_created);
+
 CREATE TABLE IF NOT EXISTS deliveries(
     order_uid   VARCHAR NOT NULL PRIMARY KEY,
     name        VARCHAR,
@@ -42,6 +53,8 @@ CREATE TABLE IF NOT EXISTS items(
         ON DELETE CASCADE
 );
 
+-- Reconciliation lookups (GET /payments/by-transaction/{transaction_id}) use the primary key
+-- index on transaction_id, so no separate index is needed.
 CREATE TABLE IF NOT EXISTS payments(
     transaction_id  VARCHAR NOT NULL PRIMARY KEY,
     request_id      VARCHAR,
@@ -56,3 +69,27 @@ CREATE TABLE IF NOT EXISTS payments(
     FOREIGN KEY (transaction_id) REFERENCES orders (order_uid)
         ON DELETE CASCADE
 );
+
+-- Monetary columns used to be INTEGER and overflowed for large orders, and some versions created
+-- sm_id and sale as INTEGER as well. Widen only the columns that are still INTEGER: ALTER ... TYPE
+-- locks the table exclusively and rewrites it, which must not happen on every startup.
+-- TODO: amounts are integers in the smallest currency unit; fractional amounts need NUMERIC
+-- columns and a decimal type in `order.rs`.
+DO $$
+DECLARE
+    col record;
+BEGIN
+    FOR col IN
+        SELECT table_name, column_name
+        FROM information_schema.columns
+        WHERE table_schema = current_schema()
+          AND data_type = 'integer'
+          AND (table_name, column_name) IN (
+              ('payments', 'amount'), ('payments', 'delivery_cost'), ('payments', 'goods_total'),
+              ('items', 'price'), ('items', 'total_price'), ('items', 'sale'), ('orders', 'sm_id')
+          )
+    LOOP
+        EXECUTE format('ALTER TABLE %I ALTER COLUMN %I TYPE BIGINT', col.table_name, col.column_name);
+    END LOOP;
+END
+$$;
@@ -0,0 +1,42 @@
+use rand::Rng;
+use std::time::Duration;
+use tokio_postgres::error::{Error as PostgresError, SqlState};
+
+/// How often and how long to wait before retrying a failed database write.
+/// - `max_retries`: The number of retries after the first attempt; `0` disables retrying.
+/// - `base_delay`: The delay before the first retry; every further retry waits twice as long.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `retry` (starting at `0`): `base_delay * 2^retry`,
+    /// reduced by a random jitter of up to a half, so that concurrent writers don't retry in lockstep.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(retry));
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Whether `e` is likely to go away on its own, so that the operation is worth retrying:
+/// lost or refused connections, serialization failures and deadlocks, and a server that is
+/// shutting down or out of connections. Constraint violations and other errors in the data
+/// are not retried, and neither are statements cancelled by `statement_timeout`.
+pub fn is_transient(e: &PostgresError) -> bool {
+    let Some(code) = e.code() else {
+        // No SQLSTATE means the error happened on the client side, e.g. the connection broke.
+        return e.is_closed() || std::error::Error::source(e).is_some_and(|source| source.is::<std::io::Error>());
+    };
+
+    code.code().starts_with("08")  // connection_exception
+        || [
+            SqlState::T_R_SERIALIZATION_FAILURE,
+            SqlState::T_R_DEADLOCK_DETECTED,
+            SqlState::ADMIN_SHUTDOWN,
+            SqlState::CRASH_SHUTDOWN,
+            SqlState::CANNOT_CONNECT_NOW,
+            SqlState::TOO_MANY_CONNECTIONS,
+        ].contains(code)
+}
@@ -1,63 +1,1516 @@
 use axum::{
-    extract::State, 
-    response::IntoResponse, 
+    body::Body,
+    async_trait,
+    extract::{rejection::{JsonRejection, PathRejection, QueryRejection}, ConnectInfo, FromRequestParts, Path, Query, Request, State}, 
+    middleware::Next,
+    response::{sse::{Event, KeepAlive, Sse}, Html, IntoResponse, Response}, 
     Json, 
     Router, 
-    http::StatusCode, 
-    routing::get
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode}, 
+    routing::{get, post}
 };
-use crate::state::AppStateType;
+use crate::state::{AddOrderError, AppStateType};
+use crate::error::{data_error_message, database_error_status, ApiError};
 use crate::order::Order;
+use crate::rate_limit::RateLimiter;
+use crate::idempotency::{Reservation, StoredResponse};
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 use serde_json::json;
-use log::error as cry;
+use log::{info, warn, error as cry};
+use futures_util::{stream, StreamExt};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast::error::RecvError, mpsc, oneshot};
+use subtle::ConstantTimeEq;
+
+/// The OpenAPI description of the API, served at `GET /openapi.json`.
+/// Kept by hand in sync with the handlers below and the types in `order.rs`.
+const OPENAPI_SPEC: &str = include_str!("resources/openapi.json");
+
+/// The Swagger UI page rendering `OPENAPI_SPEC`, served at `GET /docs`.
+const DOCS_PAGE: &str = include_str!("resources/docs.html");
+
+/// How long the health check waits for the database before reporting it as unavailable.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Number of orders returned by `GET /orders` when no `limit` is given.
+const DEFAULT_PAGE_LIMIT: i64 = 20;
+
+/// Maximum number of orders returned by `GET /orders`; larger limits are capped to it.
+const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Minimum length, in characters, of the text looked up by `GET /orders/search`; shorter text
+/// matches too many orders to be useful.
+const MIN_SEARCH_LENGTH: usize = 3;
+
+/// Maximum length, in characters, of the text looked up by `GET /orders/search`; names, emails,
+/// and track numbers are far shorter.
+const MAX_SEARCH_LENGTH: usize = 100;
+
+/// Number of order batches buffered between the database cursor and the `GET /orders/export` response.
+const EXPORT_CHANNEL_CAPACITY: usize = 4;
+
+/// The header carrying the client's idempotency key of `POST /order`, see `idempotent_submissions`.
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Maximum length of an `Idempotency-Key`; UUIDs and similar keys are far shorter.
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Number of response lines buffered between an upload to `POST /orders/stream` and its client.
+const UPLOAD_CHANNEL_CAPACITY: usize = 16;
+
+/// Number of lines of an upload to `POST /orders/stream` between two progress lines of the response.
+const UPLOAD_PROGRESS_INTERVAL: usize = 1000;
+
+/// Maximum length of a line of an upload to `POST /orders/stream`; a single order is far smaller,
+/// so a longer line means the body is not newline-delimited.
+const MAX_UPLOAD_LINE_BYTES: usize = 1024 * 1024;
+
+/// Maximum number of skipped lines listed with their errors in the summary of an upload.
+const MAX_REPORTED_UPLOAD_ERRORS: usize = 100;
+
+/// Query parameters of `GET /orders`.
+/// - `limit`: Maximum number of orders to return, `DEFAULT_PAGE_LIMIT` by default.
+/// - `offset`: Number of orders to skip, `0` by default.
+/// - `from`, `to`: An RFC 3339 time window the orders were received in; both or neither are given,
+///   and `offset` can't be combined with them.
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+/// Query parameters of `POST /order`.
+/// - `dry_run`: Only validate the order without queueing it, `false` by default.
+///   The `X-Dry-Run: true` header has the same effect.
+#[derive(Deserialize)]
+struct SubmitParams {
+    dry_run: Option<bool>,
+}
+
+/// Query parameters of `GET /order`.
+/// - `position`: Which end of the in-memory queue to return, `newest` by default.
+#[derive(Deserialize)]
+struct PositionParams {
+    position: Option<QueuePosition>,
+}
+
+/// An end of the in-memory order queue.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum QueuePosition {
+    /// The order queued first (`oldest`).
+    Oldest,
+    /// The order queued last (`newest`).
+    #[default]
+    Newest,
+}
+
+/// Query parameter of the read endpoints choosing the layout of the JSON response, see `JsonFormat`.
+#[derive(Deserialize)]
+struct FormatParams {
+    pretty: Option<bool>,
+}
+
+/// Query parameters of `GET /orders/by-customer/:customer_id`.
+/// - `limit`: Maximum number of orders to return, `DEFAULT_PAGE_LIMIT` by default.
+#[derive(Deserialize)]
+struct LimitParams {
+    limit: Option<i64>,
+}
+
+/// Query parameters of `GET /orders/search`.
+/// - `q`: The text to look for.
+/// - `limit`: Maximum number of orders to return, `DEFAULT_PAGE_LIMIT` by default.
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<i64>,
+}
+
+/// Query parameters of `POST /orders/stream`.
+/// - `on_error`: What to do with a malformed or invalid line, `stop` by default.
+#[derive(Deserialize)]
+struct UploadParams {
+    on_error: Option<OnError>,
+}
+
+/// How an upload to `POST /orders/stream` treats a line that is not a valid order.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum OnError {
+    /// Stop reading the upload at the line (`stop`).
+    #[default]
+    Stop,
+    /// Skip the line and count it in the summary (`skip`).
+    Skip,
+}
+
+/// The body of `POST /admin/read-only`.
+/// - `enabled`: Whether new and corrected orders should be refused.
+#[derive(Deserialize)]
+struct ReadOnlyToggle {
+    enabled: bool,
+}
 
 /// Creates a router that handles order-related HTTP requests.
 ///
 /// # Routes:
 /// - `GET /order`: Retrieves the last order from the server's in-memory queue.
 /// - `POST /order`: Accepts a new order and adds it to the server's in-memory queue.
+/// - `POST /orders`: Accepts a batch of orders and adds the valid ones to the in-memory queue.
+/// - `GET /order/:order_uid`: Retrieves a specific order from the in-memory queue or the database.
+/// - `HEAD /order/:order_uid`: Reports whether a specific order exists, without a body.
+/// - `PUT /order/:order_uid`: Replaces a previously submitted order with a corrected version.
+/// - `GET /order/:order_uid/items`: Retrieves only the line items of a specific order.
+/// - `POST /order/:order_uid/anonymize`: Scrubs the personal data of a specific order, keeping the record.
+/// - `POST /order/:order_uid/diff`: Compares a candidate version of a specific order with the known one.
+/// - `GET /orders`: Lists stored orders page by page, most recent first, or those received within a time window.
+/// - `GET /orders/count`: Reports the number of stored orders and of orders pending a flush.
+/// - `GET /orders/stream`: Streams newly accepted orders as Server-Sent Events.
+/// - `POST /orders/stream`: Accepts newline-delimited orders, queueing each one as it arrives.
+/// - `GET /orders/latest/:n`: Retrieves up to `n` of the most recent orders, queued or stored.
+/// - `GET /orders/export`: Streams all stored orders as newline-delimited JSON.
+/// - `GET /orders/by-customer/:customer_id`: Lists the stored orders of a customer, most recent first.
+/// - `GET /orders/search`: Finds stored orders by a part of the recipient's name or email or of the track number.
+/// - `GET /payments/by-transaction/:transaction_id`: Retrieves a stored payment and its order's identifier.
+/// - `POST /admin/flush/:order_uid`: Saves a single buffered order to the database right away.
+/// - `POST /admin/read-only`: Stops or resumes accepting new and corrected orders.
+/// - `GET /health`: Reports whether the database is reachable.
+/// - `GET /version`: Reports the version of the service and the commit it was built from.
+/// - `GET /stats`: Reports the queue and flush counters as a JSON snapshot.
+/// - `GET /stats/delivery-services`: Counts the stored orders of every delivery service.
+/// - `GET /metrics`: Exposes runtime metrics in the Prometheus text format.
+/// - `GET /openapi.json`: Describes the API in the OpenAPI 3 format.
+/// - `GET /schema/order`: Describes the `Order` payload as a JSON Schema.
+/// - `GET /docs`: Renders the API description with Swagger UI.
 ///
-/// This function sets up two routes: one for fetching the most recent order (GET),
-/// and one for submitting a new order (POST). Orders are processed and saved to the database
-/// if needed.
+/// This function sets up routes for fetching the most recent order (GET), fetching a specific
+/// order by its identifier (GET), listing stored orders (GET), submitting a new order or a batch
+/// of orders (POST), a readiness check (GET), and metrics (GET).
+/// Orders are processed and saved to the database if needed.
 pub fn handle_order() -> Router<AppStateType> {
     
     /// Handles the `POST /order` route to accept a new order. The order is passed in as a JSON payload.
+    /// With `?dry_run=true` or an `X-Dry-Run: true` header the order is only validated and never queued.
     ///
     /// # Parameters:
     /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
-    /// - `order`: The new `Order` submitted by the client.
+    /// - `params`: The query parameters, see `SubmitParams`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    /// - `order`: The new `Order` submitted by the client, or the reason it couldn't be parsed.
     ///
     /// # Returns:
-    /// - `StatusCode::OK` with a success message if the order is added successfully.
-    /// - `StatusCode::INTERNAL_SERVER_ERROR` if an error occurs while saving the order to the database.
-    async fn send_order(State(state): State<AppStateType>, Json(order): Json<Order>) -> impl IntoResponse {
-        match state.add_order(order).await {
-            Ok(_) => (StatusCode::OK, "Order received!").into_response(),
+    /// - `StatusCode::CREATED` with `{"order_uid": "..."}` and a `Location: /order/{order_uid}` header
+    ///   if the order is added successfully.
+    /// - `StatusCode::OK` with the same body and headers and `X-Duplicate: true` if the order is
+    ///   still queued or was accepted recently; it is not queued again.
+    /// - `StatusCode::OK` with `{"valid": true}` if the order is valid in a dry run.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if the query string is malformed;
+    ///   - `invalid_body` if the body is not a valid `Order` JSON;
+    ///   - `invalid_order` with every violation in `details` if the order is invalid;
+    ///   - `queue_full` with a `Retry-After` header if the queue has reached its hard cap and
+    ///     can't be flushed to the database;
+    ///   - `journal_error` if the order can't be recorded in the journal;
+    ///   - `database_error`, `database_timeout`, `database_unavailable`, `constraint_violation`, or `invalid_data` if the
+    ///     order can't be saved in the write-through persistence mode.
+    async fn send_order(
+        State(state): State<AppStateType>,
+        params: Result<Query<SubmitParams>, QueryRejection>,
+        headers: HeaderMap,
+        order: Result<Json<Order>, JsonRejection>,
+    ) -> Result<Response, ApiError> {
+        let request_id = request_id(&headers);
+        let Query(params) = params?;
+        let Json(mut order) = order?;
+        if state.normalize_input() {
+            order.normalize();
+        }
+
+        let dry_run = params.dry_run.unwrap_or(false) || headers.get("x-dry-run")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        if !dry_run {
+            if state.read_only() {
+                info!("request_id={} Refused order {} in read-only mode", request_id, order.order_uid);
+                return Err(ApiError::ReadOnly);
+            }
+            state.metrics().inc_orders_received();
+        }
+
+        if let Err(violations) = order.validate(state.validation_rules()) {
+            info!("request_id={} Rejected order {}: {}", request_id, order.order_uid, violations.join("; "));
+            return Err(ApiError::InvalidOrder(violations));
+        }
+        if dry_run {
+            info!("request_id={} Validated order {} (dry run)", request_id, order.order_uid);
+            return Ok((StatusCode::OK, Json(json!({"valid": true}))).into_response());
+        }
+
+        let order_uid = order.order_uid.clone();
+        let queued = state.add_order(order).await.inspect_err(|e| {
+            cry!("request_id={} Refused order {}: {}", request_id, order_uid, e);
+        })?;
+
+        let location = [(header::LOCATION, format!("/order/{order_uid}"))];
+        if !queued {
+            info!("request_id={} Skipped duplicate order {}", request_id, order_uid);
+            return Ok((
+                StatusCode::OK,
+                location,
+                [("x-duplicate", "true")],
+                Json(json!({"order_uid": order_uid})),
+            ).into_response());
+        }
+
+        info!("request_id={} Accepted order {}", request_id, order_uid);
+        Ok((StatusCode::CREATED, location, Json(json!({"order_uid": order_uid}))).into_response())
+    }
+
+    /// Handles the `PUT /order/:order_uid` route to replace a previously submitted order with a
+    /// corrected version passed as a JSON payload.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the order to replace.
+    /// - `order`: The new version of the order, or the reason it couldn't be parsed.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"order_uid": "..."}` if the order was replaced.
+    /// - An `ApiError` otherwise, as from `POST /order`:
+    ///   - `read_only` if the service is in read-only mode;
+    ///   - `invalid_body` if the body is not an `Order`, or `invalid_order` with the violated rules
+    ///     in `details` if the order is invalid;
+    ///   - `conflict` if the uid in the path differs from the one in the body;
+    ///   - `not_found` if no order with this uid is known;
+    ///   - `constraint_violation` or `invalid_data` if the database rejects the order, and
+    ///     `database_error`, `database_timeout`, or `database_unavailable` if writing to it fails.
+    async fn update_order(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        order: Result<Json<Order>, JsonRejection>,
+    ) -> Result<Response, ApiError> {
+        if state.read_only() {
+            return Err(ApiError::ReadOnly);
+        }
+        let Json(mut order) = order?;
+        if state.normalize_input() {
+            order.normalize();
+        }
+
+        if order.order_uid != order_uid {
+            return Err(ApiError::Conflict(format!(
+                "order_uid in the body ({}) doesn't match the path ({})", order.order_uid, order_uid
+            )));
+        }
+        order.validate(state.validation_rules()).map_err(ApiError::InvalidOrder)?;
+
+        if !state.update_order(order).await.inspect_err(|e| cry!("Database error: {}", e))? {
+            return Err(ApiError::NotFound(format!("no order with uid {order_uid}")));
+        }
+        Ok((StatusCode::OK, Json(json!({"order_uid": order_uid}))).into_response())
+    }
+
+    /// Handles the `POST /orders` route to accept a batch of orders passed as a JSON array.
+    /// Every order is validated separately; the valid ones are queued and the invalid ones are reported.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `orders`: The submitted orders, or the reason they couldn't be parsed.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"accepted": N, "rejected": [...]}`, where every rejected entry holds
+    ///   the `index` of the order in the batch, its `order_uid`, and the `errors` found in it.
+    /// - An `ApiError` otherwise:
+    ///   - `read_only` if the service is in read-only mode;
+    ///   - `invalid_body` if the body is not a JSON array of orders;
+    ///   - `queue_full`, `journal_error`, or one of the database codes if an order can't be queued,
+    ///     with the same summary next to `error`; the orders that were not queued are reported as
+    ///     rejected.
+    async fn send_orders(
+        State(state): State<AppStateType>,
+        orders: Result<Json<Vec<Order>>, JsonRejection>,
+    ) -> Result<Response, ApiError> {
+        if state.read_only() {
+            return Err(ApiError::ReadOnly);
+        }
+        let Json(orders) = orders?;
+
+        let mut valid = Vec::with_capacity(orders.len());
+        let mut rejected = Vec::new();
+        for (index, mut order) in orders.into_iter().enumerate() {
+            state.metrics().inc_orders_received();
+            if state.normalize_input() {
+                order.normalize();
+            }
+            match order.validate(state.validation_rules()) {
+                Ok(()) => valid.push((index, order)),
+                Err(errors) => rejected.push(json!({"index": index, "order_uid": order.order_uid, "errors": errors})),
+            }
+        }
+
+        let (indices, valid): (Vec<_>, Vec<_>) = valid.into_iter().unzip();
+        let uids: Vec<String> = valid.iter().map(|order| order.order_uid.clone()).collect();
+        let (accepted, result) = state.add_orders(valid).await;
+
+        match result {
+            Ok(()) => Ok((StatusCode::OK, Json(json!({"accepted": accepted, "rejected": rejected}))).into_response()),
+            Err(e) => {
+                cry!("Refused {} orders: {}", uids.len() - accepted, e);
+                let error = ApiError::from(e);
+                let reason = error.message();
+                for (index, order_uid) in indices.into_iter().zip(uids).skip(accepted) {
+                    rejected.push(json!({
+                        "index": index,
+                        "order_uid": order_uid,
+                        "errors": [reason],
+                    }));
+                }
+
+                let mut summary = serde_json::Map::new();
+                summary.insert("accepted".to_string(), accepted.into());
+                summary.insert("rejected".to_string(), rejected.into());
+                Ok(error.into_response_with(summary))
+            }
+        }
+    }
+
+    /// Handles the `GET /order` route to fetch the last order from the in-memory queue, or the
+    /// first one with `?position=oldest`. Both read the in-memory queue only; just for the newest
+    /// order, the latest stored order is returned instead if the queue is empty (e.g. right after a flush).
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `params`: The query parameters, see `PositionParams`.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON representation of the order, if one exists.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if `position` is neither `oldest` nor `newest`;
+    ///   - `not_found` if there is no such order, i.e. the queue is empty and so is the database;
+    ///   - `database_timeout` or `database_unavailable` if the queue is empty and reading from the
+    ///     database fails, as it is then unknown whether there are orders.
+    async fn get_order(
+        State(state): State<AppStateType>,
+        params: Result<Query<PositionParams>, QueryRejection>,
+        format: JsonFormat,
+        headers: HeaderMap,
+    ) -> Result<Response, ApiError> {
+        let request_id = request_id(&headers);
+        let Query(params) = params?;
+
+        let order = match params.position.unwrap_or_default() {
+            QueuePosition::Oldest => state.get_first_order().await,
+            QueuePosition::Newest => match state.get_last_order().await {
+                Some(order) => Some(order),
+                None => match state.latest_persisted_order().await {
+                    Ok(order) => order,
+                    Err(e) => {
+                        cry!("request_id={} Database error: {}", request_id, e);
+                        // Not a server error: the stored orders are only out of reach for now.
+                        return Err(match database_error_status(&e) {
+                            StatusCode::SERVICE_UNAVAILABLE => e.into(),
+                            _ => ApiError::DatabaseUnavailable(
+                                "the order queue is empty and the database can't be read, try again later".to_string(),
+                            ),
+                        });
+                    }
+                },
+            },
+        };
+
+        let Some(order) = order else {
+            info!("request_id={} No orders to return", request_id);
+            return Err(ApiError::NotFound("no orders yet".to_string()));
+        };
+
+        info!("request_id={} Returning the order {}", request_id, order.order_uid);
+        Ok(format.respond(StatusCode::OK, &order))
+    }
+
+    /// Handles the `GET /order/:order_uid` route to fetch a specific order by its unique identifier.
+    /// The in-memory queue is checked first, then the read cache, if enabled, and then the database.
+    /// With the read cache enabled, an order found in it is answered with `X-Cache: HIT` and an order
+    /// loaded from the database with `X-Cache: MISS`.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the requested order.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON representation of the order, if it is found.
+    /// - An `ApiError` otherwise:
+    ///   - `not_found` if neither source has the order;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn get_order_by_uid(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let mut cache_status = None;
+        let order = match state.find_queued_order(&order_uid).await {
+            Some(order) => Some(order),
+            None => match state.cached_order(&order_uid) {
+                Some(order) => {
+                    cache_status = Some("HIT");
+                    Some(order)
+                }
+                None => {
+                    let order = state.load_order(&order_uid).await.inspect_err(|e| cry!("Database error: {}", e))?;
+                    cache_status = state.read_cache_enabled().then_some("MISS");
+                    order
+                }
+            },
+        };
+        let Some(order) = order else {
+            return Err(ApiError::NotFound(format!("no order with uid {order_uid}")));
+        };
+
+        let mut response = format.respond(StatusCode::OK, &order);
+        if let Some(cache_status) = cache_status {
+            response.headers_mut().insert("x-cache", HeaderValue::from_static(cache_status));
+        }
+        Ok(response)
+    }
+
+    /// Handles the `HEAD /order/:order_uid` route to check whether an order exists without
+    /// transferring it. The in-memory queue is checked first, then the database.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the order.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` if the order is queued or stored.
+    /// - `StatusCode::NOT_FOUND` if it is not.
+    /// - `StatusCode::INTERNAL_SERVER_ERROR` if an error occurs while reading from the database.
+    /// - `StatusCode::SERVICE_UNAVAILABLE` instead if the database didn't respond within the configured timeouts.
+    async fn order_exists(State(state): State<AppStateType>, Path(order_uid): Path<String>) -> StatusCode {
+        match state.order_exists(&order_uid).await {
+            Ok(true) => StatusCode::OK,
+            Ok(false) => StatusCode::NOT_FOUND,
             Err(e) => {
                 cry!("Database error: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save order to database").into_response()
+                database_error_status(&e)
+            }
+        }
+    }
+
+    /// Handles the `GET /order/:order_uid/items` route to fetch only the line items of an order.
+    /// The in-memory queue is checked first, then the database.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the order.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with a JSON array of the order's items, empty if the order has none.
+    /// - An `ApiError` otherwise:
+    ///   - `not_found` if the order is unknown;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn get_order_items(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let items = match state.find_queued_order(&order_uid).await {
+            Some(order) => Some(order.items),
+            None => state.load_items(&order_uid).await.inspect_err(|e| cry!("Database error: {}", e))?,
+        };
+
+        match items {
+            Some(items) => Ok(format.respond(StatusCode::OK, &items)),
+            None => Err(ApiError::NotFound(format!("no order with uid {order_uid}"))),
+        }
+    }
+
+    /// Handles the `GET /orders?limit=&offset=` route to list stored orders, most recent first.
+    /// With `?from=&to=` (RFC 3339), the orders received within that window are listed instead,
+    /// oldest first (see `AppState::orders_in_range`).
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `params`: The page or window to return; `limit` defaults to `DEFAULT_PAGE_LIMIT` and is capped at `MAX_PAGE_LIMIT`.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON array of orders.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if the query parameters are malformed, `limit` is not positive, `offset`
+    ///     is negative, only one of `from` and `to` is given, `from` is after `to`, or `offset` is
+    ///     combined with a window;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn list_orders(
+        State(state): State<AppStateType>,
+        params: Result<Query<PageParams>, QueryRejection>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let Query(params) = params?;
+
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        let offset = params.offset.unwrap_or(0);
+        if limit <= 0 {
+            return Err(ApiError::InvalidParameter("limit must be positive".to_string()));
+        }
+        if offset < 0 {
+            return Err(ApiError::InvalidParameter("offset must not be negative".to_string()));
+        }
+
+        let orders = match (params.from, params.to) {
+            (None, None) => state.list_orders(limit.min(MAX_PAGE_LIMIT), offset).await,
+            (Some(from), Some(to)) => {
+                if from > to {
+                    return Err(ApiError::InvalidParameter("from must not be after to".to_string()));
+                }
+                if params.offset.is_some() {
+                    return Err(ApiError::InvalidParameter("offset can't be combined with from and to".to_string()));
+                }
+                state.orders_in_range(from, to, limit.min(MAX_PAGE_LIMIT)).await
+            }
+            _ => return Err(ApiError::InvalidParameter("from and to must be given together".to_string())),
+        };
+
+        let orders = orders.inspect_err(|e| cry!("Database error: {}", e))?;
+        Ok(format.respond(StatusCode::OK, &orders))
+    }
+
+    /// Handles the `GET /orders/by-customer/:customer_id?limit=` route to look up a customer's
+    /// stored orders, most recent first.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `customer_id`: The customer whose orders are returned.
+    /// - `params`: `limit` defaults to `DEFAULT_PAGE_LIMIT` and is capped at `MAX_PAGE_LIMIT`.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON array of orders, empty if the customer has none.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if the query is malformed or `limit` is not positive;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn orders_by_customer(
+        State(state): State<AppStateType>,
+        Path(customer_id): Path<String>,
+        params: Result<Query<LimitParams>, QueryRejection>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let Query(params) = params?;
+
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if limit <= 0 {
+            return Err(ApiError::InvalidParameter("limit must be positive".to_string()));
+        }
+
+        let orders = state.orders_by_customer(&customer_id, limit.min(MAX_PAGE_LIMIT)).await
+            .inspect_err(|e| cry!("Database error: {}", e))?;
+        Ok(format.respond(StatusCode::OK, &orders))
+    }
+
+    /// Handles the `GET /orders/search?q=&limit=` route used by support staff to find stored orders
+    /// by a part of the recipient's name (`delivery.name`) or email (`delivery.email`) or of the
+    /// track number (`orders.track_number`), most recent first. The text is matched
+    /// case-insensitively and literally, wildcards included.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `params`: `q` is trimmed and must be `MIN_SEARCH_LENGTH` to `MAX_SEARCH_LENGTH` characters long;
+    ///   `limit` defaults to `DEFAULT_PAGE_LIMIT` and is capped at `MAX_PAGE_LIMIT`.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON array of orders, empty if none match.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if the query is malformed, `q` is too short or too long, or `limit` is not positive;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn search_orders(
+        State(state): State<AppStateType>,
+        params: Result<Query<SearchParams>, QueryRejection>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let Query(params) = params?;
+
+        let text = params.q.trim();
+        if !(MIN_SEARCH_LENGTH..=MAX_SEARCH_LENGTH).contains(&text.chars().count()) {
+            return Err(ApiError::InvalidParameter(format!(
+                "q must be {MIN_SEARCH_LENGTH} to {MAX_SEARCH_LENGTH} characters long"
+            )));
+        }
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if limit <= 0 {
+            return Err(ApiError::InvalidParameter("limit must be positive".to_string()));
+        }
+
+        let orders = state.search_orders(text, limit.min(MAX_PAGE_LIMIT)).await.inspect_err(|e| cry!("Database error: {}", e))?;
+        Ok(format.respond(StatusCode::OK, &orders))
+    }
+
+    /// Handles the `POST /order/:order_uid/anonymize` route used to honor GDPR erasure requests
+    /// without losing the order for accounting: the recipient's name, phone, address, and email
+    /// and the `customer_id` are replaced with placeholders (see `Order::anonymize`), while the
+    /// items and the payment stay intact.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the order to anonymize.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with the anonymized order.
+    /// - An `ApiError` otherwise:
+    ///   - `not_found` if no order with this uid is known;
+    ///   - `read_only` if the service is in read-only mode;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if writing to the database fails.
+    async fn anonymize_order(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        format: JsonFormat,
+        headers: HeaderMap,
+    ) -> Result<Response, ApiError> {
+        let request_id = request_id(&headers);
+        if state.read_only() {
+            return Err(ApiError::ReadOnly);
+        }
+
+        let order = state.anonymize_order(&order_uid).await.inspect_err(|e| {
+            cry!("request_id={} Failed to anonymize order {}: {}", request_id, order_uid, e);
+        })?;
+
+        match order {
+            Some(order) => {
+                info!("request_id={} Anonymized order {}", request_id, order_uid);
+                Ok(format.respond(StatusCode::OK, &order))
+            }
+            None => Err(ApiError::NotFound(format!("no order with uid {order_uid}"))),
+        }
+    }
+
+    /// Handles the `POST /order/:order_uid/diff` route to find out what a `PUT` with the submitted
+    /// order would change. The candidate is compared with the queued version of the order, if any,
+    /// or else with the stored one (see `Order::diff`); nothing is modified. The candidate is
+    /// normalized like a submitted order, but not validated, and the `order_uid` in the body is ignored.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the known order.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    /// - `order`: The candidate version of the order, or the reason it couldn't be parsed.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"order_uid": "...", "changed": bool, "diff": {...}}`, see `OrderDiff`.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_body` if the body is not a valid `Order` JSON;
+    ///   - `not_found` if no order with this uid is known;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn diff_order(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        format: JsonFormat,
+        order: Result<Json<Order>, JsonRejection>,
+    ) -> Result<Response, ApiError> {
+        let Json(mut candidate) = order?;
+        if state.normalize_input() {
+            candidate.normalize();
+        }
+
+        let known = match state.find_queued_order(&order_uid).await {
+            Some(order) => Some(order),
+            None => match state.cached_order(&order_uid) {
+                Some(order) => Some(order),
+                None => state.load_order(&order_uid).await.inspect_err(|e| {
+                    cry!("Failed to load order {} to compare: {}", order_uid, e);
+                })?,
+            },
+        };
+        let Some(known) = known else {
+            return Err(ApiError::NotFound(format!("no order with uid {order_uid}")));
+        };
+
+        let diff = known.diff(&candidate);
+        Ok(format.respond(StatusCode::OK, &json!({"order_uid": order_uid, "changed": !diff.is_empty(), "diff": diff})))
+    }
+
+    /// Handles the `GET /payments/by-transaction/:transaction_id` route used to reconcile payments,
+    /// which finance teams track by transaction ID rather than by order. Only stored payments are found.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `transaction_id`: The transaction ID of the payment.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"order_uid": "...", "payment": {...}}`, if the payment is stored.
+    /// - An `ApiError` otherwise:
+    ///   - `not_found` if no stored payment has this transaction ID;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn payment_by_transaction(
+        State(state): State<AppStateType>,
+        Path(transaction_id): Path<String>,
+        format: JsonFormat,
+        headers: HeaderMap,
+    ) -> Result<Response, ApiError> {
+        let record = state.payment_by_transaction(&transaction_id).await.inspect_err(|e| {
+            cry!("request_id={} Database error: {}", request_id(&headers), e);
+        })?;
+
+        match record {
+            Some(record) => Ok(format.respond(StatusCode::OK, &record)),
+            None => Err(ApiError::NotFound(format!("no payment with transaction ID {transaction_id}"))),
+        }
+    }
+
+    /// Handles the `GET /orders/export` route used for backups and ETL. Streams every stored order
+    /// as newline-delimited JSON (one `Order` per line) while reading them from a database cursor,
+    /// so the response is never held in memory as a whole.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with an `application/x-ndjson` body. If a database error occurs midway,
+    ///   the body is aborted, so a truncated export can't be mistaken for a complete one.
+    async fn export_orders(State(state): State<AppStateType>) -> impl IntoResponse {
+        let (sink, batches) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+        let (errors_sink, errors) = oneshot::channel();
+
+        tokio::spawn(async move {
+            if let Err(e) = state.export_orders(sink).await {
+                cry!("Database error during export: {}", e);
+                let _ = errors_sink.send(e);
+            }
+        });
+
+        let lines = stream::unfold((batches, Some(errors)), |(mut batches, errors)| async move {
+            match batches.recv().await {
+                Some(orders) => {
+                    let mut chunk = Vec::new();
+                    for order in &orders {
+                        serde_json::to_writer(&mut chunk, order).unwrap();
+                        chunk.push(b'\n');
+                    }
+                    Some((Ok(chunk), (batches, errors)))
+                }
+                // The export is over; report its error, if any, to abort the body.
+                None => match errors?.await {
+                    Ok(e) => Some((Err(e), (batches, None))),
+                    Err(_) => None,
+                },
+            }
+        });
+
+        ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines))
+    }
+
+    /// Handles the `POST /orders/stream` route for batches too large to be buffered. The body is
+    /// newline-delimited JSON (one `Order` per line) that is parsed, validated, and queued line by
+    /// line while it arrives; empty lines are ignored. Unlike the other routes, the body size is not
+    /// limited, only the length of a line (`MAX_UPLOAD_LINE_BYTES`).
+    ///
+    /// The response is newline-delimited JSON as well: every `UPLOAD_PROGRESS_INTERVAL` lines a progress
+    /// line `{"lines": N, "accepted": N, "duplicates": N, "skipped": N}`, dropped if the client doesn't
+    /// read the response while uploading, and finally the summary (see `UploadSummary`).
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `params`: The query parameters, see `UploadParams`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    /// - `body`: The newline-delimited orders.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with an `application/x-ndjson` body. The status is sent before the upload
+    ///   is read, so whether it stopped early is only told by the `error` of the summary.
+    /// - An `ApiError` with `invalid_query` if the query string is malformed.
+    async fn upload_orders(
+        State(state): State<AppStateType>,
+        params: Result<Query<UploadParams>, QueryRejection>,
+        headers: HeaderMap,
+        body: Body,
+    ) -> Result<Response, ApiError> {
+        let Query(params) = params?;
+        if state.read_only() {
+            return Err(ApiError::ReadOnly);
+        }
+        let on_error = params.on_error.unwrap_or_default();
+        let request_id = request_id(&headers).to_string();
+        let (sink, lines) = mpsc::channel(UPLOAD_CHANNEL_CAPACITY);
+
+        tokio::spawn(async move {
+            let summary = UploadSummary::ingest(&state, body, on_error, &request_id, &sink).await;
+            match &summary.error {
+                Some(e) => warn!(
+                    "request_id={} Stopped the upload after {} lines ({} orders accepted): {}",
+                    request_id, summary.lines, summary.accepted, e
+                ),
+                None => info!(
+                    "request_id={} Uploaded {} lines: {} orders accepted, {} duplicates, {} skipped",
+                    request_id, summary.lines, summary.accepted, summary.duplicates, summary.skipped
+                ),
+            }
+            let _ = sink.send(json!(summary)).await;
+        });
+
+        let lines = stream::unfold(lines, |mut lines| async move {
+            let line = lines.recv().await?;
+            let mut chunk = serde_json::to_vec(&line).unwrap();
+            chunk.push(b'\n');
+            Some((Ok::<_, Infallible>(chunk), lines))
+        });
+
+        Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+    }
+
+    /// Handles the `GET /orders/latest/:n` route to fetch a window of the most recent orders,
+    /// taken from the in-memory queue first and topped up from the database.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `n`: The number of orders to return; capped at `MAX_PAGE_LIMIT`.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` and a JSON array of up to `n` orders, newest first.
+    /// - An `ApiError` otherwise:
+    ///   - `invalid_query` if `n` is not a positive number;
+    ///   - `database_error`, `database_timeout`, or `database_unavailable` if reading from the database fails.
+    async fn latest_orders(
+        State(state): State<AppStateType>,
+        n: Result<Path<usize>, PathRejection>,
+        format: JsonFormat,
+    ) -> Result<Response, ApiError> {
+        let n = match n {
+            Ok(Path(n)) if n > 0 => n,
+            _ => return Err(ApiError::InvalidParameter("n must be a positive number".to_string())),
+        };
+
+        let orders = state.latest_orders(n.min(MAX_PAGE_LIMIT as usize)).await
+            .inspect_err(|e| cry!("Database error: {}", e))?;
+        Ok(format.respond(StatusCode::OK, &orders))
+    }
+
+    /// Handles the `GET /orders/stream` route, a Server-Sent Events feed of the orders accepted
+    /// after the client connected. Every order is sent as an `order` event with the order JSON as data.
+    /// A client that can't keep up is disconnected with a logged warning, so it can reconnect
+    /// instead of silently missing orders; producers never wait for it.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with a `text/event-stream` body that stays open until the client disconnects.
+    async fn stream_orders(State(state): State<AppStateType>) -> impl IntoResponse {
+        let events = stream::unfold(state.subscribe(), |mut orders| async move {
+            match orders.recv().await {
+                Ok(order) => {
+                    let event = Event::default().event("order").json_data(&order).unwrap();
+                    Some((Ok::<_, Infallible>(event), orders))
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Disconnecting a slow order stream subscriber that missed {} orders", missed);
+                    None
+                }
+                Err(RecvError::Closed) => None,
+            }
+        });
+
+        Sse::new(events).keep_alive(KeepAlive::default())
+    }
+
+    /// Handles the `GET /orders/count` route used by dashboards.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"persisted": N, "buffered": M}`, where `persisted` is the number of
+    ///   orders stored in the database and `buffered` the number of queued orders pending a flush.
+    /// - An `ApiError` with `database_error`, `database_timeout`, or `database_unavailable` if
+    ///   reading from the database fails.
+    async fn count_orders(State(state): State<AppStateType>, format: JsonFormat) -> Result<Response, ApiError> {
+        let persisted = state.count_orders().await.inspect_err(|e| cry!("Database error: {}", e))?;
+        let buffered = state.count_pending().await;
+        Ok(format.respond(StatusCode::OK, &json!({"persisted": persisted, "buffered": buffered})))
+    }
+
+    /// Handles the `POST /admin/flush` route used during maintenance to save the buffered orders
+    /// to the database right away instead of waiting for the queue to fill up. Flushes are
+    /// serialized, so it never overlaps with an automatic one; new orders are accepted meanwhile.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"flushed": N}`, the number of orders saved to the database.
+    /// - An `ApiError` with `database_error`, `constraint_violation`, `invalid_data`, `database_timeout`,
+    ///   or `database_unavailable` if writing to the database fails; the orders that were not saved stay queued.
+    async fn flush_orders(State(state): State<AppStateType>) -> Result<Response, ApiError> {
+        let flushed = state.flush_all().await.inspect_err(|e| cry!("Database error: {}", e))?;
+        info!("Flushed {} orders to the database on request", flushed);
+        Ok((StatusCode::OK, Json(json!({"flushed": flushed}))).into_response())
+    }
+
+    /// Handles the `POST /admin/flush/:order_uid` route used to debug flush failures: saves one
+    /// buffered order to the database right away and removes it from the queue on success.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `order_uid`: The unique identifier of the order to save.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"flushed": "..."}`, the uid of the saved order.
+    /// - An `ApiError` otherwise:
+    ///   - `not_found` if the order is not waiting in the queue;
+    ///   - `database_unavailable` if it can't be saved, whatever the reason; the order stays queued
+    ///     and the message tells the reason if the database rejected the data.
+    async fn flush_order(
+        State(state): State<AppStateType>,
+        Path(order_uid): Path<String>,
+        headers: HeaderMap,
+    ) -> Result<Response, ApiError> {
+        let request_id = request_id(&headers);
+
+        match state.flush_order(&order_uid).await {
+            Ok(true) => {
+                info!("request_id={} Flushed order {} to the database on request", request_id, order_uid);
+                Ok(Json(json!({"flushed": order_uid})).into_response())
+            }
+            Ok(false) => Err(ApiError::NotFound(format!("order {order_uid} is not waiting in the queue"))),
+            Err(e) => {
+                cry!("request_id={} Failed to flush order {}: {}", request_id, order_uid, e);
+                let reason = data_error_message(&e).unwrap_or_else(|| "the database is unavailable".to_string());
+                Err(ApiError::DatabaseUnavailable(format!("failed to save order {order_uid}, it stays queued: {reason}")))
             }
         }
     }
 
-    /// Handles the `GET /order` route to fetch the last order from the in-memory queue.
+    /// Handles the `POST /admin/read-only` route used around database maintenance to stop or resume
+    /// accepting orders without a restart. The transition is logged.
     ///
     /// # Parameters:
     /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `toggle`: Whether to enter or leave the read-only mode, or the reason the body couldn't be parsed.
     ///
     /// # Returns:
-    /// - `StatusCode::OK` and a pretty-printed JSON representation of the last order, if one exists.
-    /// - If no orders are available, a message indicating that no orders have been received yet.
-    async fn get_order(State(state): State<AppStateType>) -> impl IntoResponse {
-        let pretty = match state.get_last_order().await {
-            Some(order) => serde_json::to_string_pretty(&order).unwrap(),
-            None => serde_json::to_string_pretty(&json!({"message": "No orders yet"})).unwrap(),
+    /// - `StatusCode::OK` with `{"read_only": bool}`, the mode now in effect.
+    /// - An `ApiError` with `invalid_body` if the body is not `{"enabled": bool}`.
+    async fn set_read_only(
+        State(state): State<AppStateType>,
+        toggle: Result<Json<ReadOnlyToggle>, JsonRejection>,
+    ) -> Result<Response, ApiError> {
+        let Json(toggle) = toggle?;
+        state.set_read_only(toggle.enabled);
+
+        Ok(Json(json!({"read_only": state.read_only()})).into_response())
+    }
+
+    /// Handles the `GET /health` route used by load balancers and readiness probes.
+    /// Runs `SELECT 1` against the database, giving up after `HEALTH_CHECK_TIMEOUT`; the check
+    /// bypasses the circuit breaker, whose state is reported as `circuit`.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"status": "ok", "circuit": "..."}` if the database responds in time.
+    /// - `StatusCode::SERVICE_UNAVAILABLE` with `{"status": "degraded", "circuit": "..."}` otherwise.
+    async fn health(State(state): State<AppStateType>) -> impl IntoResponse {
+        let (status, health) = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, state.ping_db()).await {
+            Ok(Ok(())) => (StatusCode::OK, "ok"),
+            Ok(Err(e)) => {
+                warn!("Health check failed: {}", e);
+                (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+            }
+            Err(_) => {
+                warn!("Health check timed out after {:?}", HEALTH_CHECK_TIMEOUT);
+                (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+            }
         };
-        (StatusCode::OK, pretty)
+        (status, Json(json!({"status": health, "circuit": state.circuit_state()})))
+    }
+
+    /// Handles the `GET /version` route used to verify which build is deployed.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"version": "...", "git_sha": "...", "built_at": "..."}`, the crate
+    ///   version, the git commit and the build time captured by `build.rs`.
+    async fn version() -> impl IntoResponse {
+        Json(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "git_sha": env!("GIT_SHA"),
+            "built_at": env!("BUILT_AT"),
+        }))
+    }
+
+    /// Handles the `GET /stats` route with a snapshot of the runtime internals for quick human
+    /// inspection, complementing `GET /metrics`.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with `{"queue_len": N, "max_capacity": N, "total_received": N, "total_flushed": N,
+    ///   "last_flush_at": "..."}`, where `last_flush_at` is `null` until the first orders are persisted.
+    async fn stats(State(state): State<AppStateType>, format: JsonFormat) -> Response {
+        format.respond(StatusCode::OK, &state.stats().await)
+    }
+
+    /// Handles the `GET /stats/delivery-services` route showing the distribution of carriers on dashboards.
+    /// Only stored orders are counted, not those pending a flush.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    /// - `format`: The layout of the JSON response, see `JsonFormat`.
+    /// - `headers`: The request headers; the `X-Request-Id` is included in the log messages.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with a JSON array of `{"service": "...", "count": N}`, largest count first.
+    /// - An `ApiError` with `database_error`, `database_timeout`, or `database_unavailable` if reading
+    ///   from the database fails.
+    async fn delivery_service_stats(
+        State(state): State<AppStateType>,
+        format: JsonFormat,
+        headers: HeaderMap,
+    ) -> Result<Response, ApiError> {
+        let counts = state.count_by_delivery_service().await.inspect_err(|e| {
+            cry!("request_id={} Database error: {}", request_id(&headers), e);
+        })?;
+
+        Ok(format.respond(StatusCode::OK, &counts))
+    }
+
+    /// Handles the `GET /metrics` route scraped by Prometheus.
+    ///
+    /// # Parameters:
+    /// - `state`: Shared application state (`AppStateType`) containing the in-memory queue and database client.
+    ///
+    /// # Returns:
+    /// - `StatusCode::OK` with the metrics in the Prometheus text exposition format.
+    async fn metrics(State(state): State<AppStateType>) -> impl IntoResponse {
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            state.metrics().render(),
+        )
+    }
+
+    /// Handles the `GET /openapi.json` route with the OpenAPI 3 description of the API.
+    async fn openapi() -> impl IntoResponse {
+        ([(header::CONTENT_TYPE, "application/json")], OPENAPI_SPEC)
+    }
+
+    /// Handles the `GET /schema/order` route with the JSON Schema of an `Order`, so that producers
+    /// can validate their payloads before submitting them. The schema is derived from the `Order`
+    /// type itself, honoring its serde attributes, so it always matches what `POST /order` parses;
+    /// the rules of `Order::validate` are not part of it.
+    async fn order_schema() -> impl IntoResponse {
+        Json(schemars::schema_for!(Order))
+    }
+
+    /// Handles the `GET /docs` route with the Swagger UI for `GET /openapi.json`.
+    async fn docs() -> impl IntoResponse {
+        Html(DOCS_PAGE)
     }
 
     // Create the router with the defined routes
     Router::new()
         .route("/order", get(get_order).post(send_order))
+        .route("/order/:order_uid", get(get_order_by_uid).head(order_exists).put(update_order))
+        .route("/order/:order_uid/items", get(get_order_items))
+        .route("/order/:order_uid/anonymize", post(anonymize_order))
+        .route("/order/:order_uid/diff", post(diff_order))
+        .route("/orders", get(list_orders).post(send_orders))
+        .route("/orders/count", get(count_orders))
+        .route("/orders/stream", get(stream_orders).post(upload_orders))
+        .route("/orders/latest/:n", get(latest_orders))
+        .route("/orders/export", get(export_orders))
+        .route("/orders/by-customer/:customer_id", get(orders_by_customer))
+        .route("/orders/search", get(search_orders))
+        .route("/payments/by-transaction/:transaction_id", get(payment_by_transaction))
+        .route("/admin/flush", post(flush_orders))
+        .route("/admin/flush/:order_uid", post(flush_order))
+        .route("/admin/read-only", post(set_read_only))
+        .route("/health", get(health))
+        .route("/version", get(version))
+        .route("/stats", get(stats))
+        .route("/stats/delivery-services", get(delivery_service_stats))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(openapi))
+        .route("/schema/order", get(order_schema))
+        .route("/docs", get(docs))
+}
+
+/// The outcome of an upload to `POST /orders/stream`, sent as the last line of the response.
+/// - `done`: Always `true`, telling the summary apart from the progress lines.
+/// - `lines`: Number of lines read, including empty ones.
+/// - `accepted`: Number of queued orders.
+/// - `duplicates`: Number of orders not queued again because they were still queued or accepted recently.
+/// - `skipped`: Number of malformed or invalid lines skipped with `?on_error=skip`.
+/// - `errors`: The `line` number and `error` of the first `MAX_REPORTED_UPLOAD_ERRORS` skipped lines.
+/// - `error`: Why the upload stopped before the end of the body, if it did.
+#[derive(Serialize, Default)]
+struct UploadSummary {
+    done: bool,
+    lines: usize,
+    accepted: usize,
+    duplicates: usize,
+    skipped: usize,
+    errors: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl UploadSummary {
+    /// Reads the newline-delimited orders of `body` as they arrive and queues them one by one,
+    /// sending a progress line to `progress` every `UPLOAD_PROGRESS_INTERVAL` lines.
+    ///
+    /// # Returns
+    /// The summary of the upload, stopped at the first line that can't be processed.
+    async fn ingest(
+        state: &AppStateType,
+        body: Body,
+        on_error: OnError,
+        request_id: &str,
+        progress: &mpsc::Sender<serde_json::Value>,
+    ) -> Self {
+        let mut summary = UploadSummary::default();
+        let mut chunks = body.into_data_stream();
+        let mut buffer = Vec::new();
+
+        'read: loop {
+            let finished = match chunks.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    false
+                }
+                Some(Err(e)) => {
+                    summary.error = Some(format!("failed to read the body: {e}"));
+                    break;
+                }
+                // The last line doesn't have to end with a newline.
+                None => {
+                    buffer.push(b'\n');
+                    true
+                }
+            };
+
+            let mut start = 0;
+            while let Some(length) = buffer[start..].iter().position(|&byte| byte == b'\n') {
+                let line = &buffer[start..start + length];
+                start += length + 1;
+
+                if let Err(e) = summary.process(state, line, on_error, request_id).await {
+                    summary.error = Some(e);
+                    break 'read;
+                }
+                if summary.lines % UPLOAD_PROGRESS_INTERVAL == 0 {
+                    // Progress is optional, so an upload never waits for a client that isn't reading.
+                    let _ = progress.try_send(json!({
+                        "lines": summary.lines,
+                        "accepted": summary.accepted,
+                        "duplicates": summary.duplicates,
+                        "skipped": summary.skipped,
+                    }));
+                }
+            }
+            buffer.drain(..start);
+
+            if buffer.len() > MAX_UPLOAD_LINE_BYTES {
+                summary.error = Some(format!(
+                    "line {} is longer than {} bytes",
+                    summary.lines + 1,
+                    MAX_UPLOAD_LINE_BYTES
+                ));
+                break;
+            }
+            if finished {
+                break;
+            }
+        }
+
+        summary.done = true;
+        summary
+    }
+
+    /// Parses, validates, and queues the order on the next line, counting the outcome.
+    ///
+    /// # Returns
+    /// `Ok(())` if the upload goes on, or the reason to stop it.
+    async fn process(&mut self, state: &AppStateType, line: &[u8], on_error: OnError, request_id: &str) -> Result<(), String> {
+        self.lines += 1;
+        if line.trim_ascii().is_empty() {
+            return Ok(());
+        }
+
+        let mut order = match serde_json::from_slice::<Order>(line) {
+            Ok(order) => order,
+            Err(e) => return self.reject(on_error, format!("malformed order: {e}")),
+        };
+        state.metrics().inc_orders_received();
+        if state.normalize_input() {
+            order.normalize();
+        }
+        if let Err(violations) = order.validate(state.validation_rules()) {
+            let error = format!("the order {} is invalid: {}", order.order_uid, violations.join("; "));
+            return self.reject(on_error, error);
+        }
+
+        let order_uid = order.order_uid.clone();
+        match state.add_order(order).await {
+            Ok(true) => self.accepted += 1,
+            Ok(false) => self.duplicates += 1,
+            Err(AddOrderError::Database(e)) if data_error_message(&e).is_some() => {
+                let error = format!("the order {} was rejected: {}", order_uid, data_error_message(&e).unwrap());
+                return self.reject(on_error, error);
+            }
+            Err(e) => {
+                cry!("request_id={} Refused order {}: {}", request_id, order_uid, e);
+                let reason = match e {
+                    AddOrderError::QueueFull(_) => "the order queue is full",
+                    AddOrderError::Journal(_) => "failed to record the order",
+                    AddOrderError::Database(_) => "failed to save the order",
+                };
+                return Err(format!("line {}: {}", self.lines, reason));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a line that is not a valid order: stops the upload, or skips the line and records
+    /// its `error`, depending on `on_error`.
+    fn reject(&mut self, on_error: OnError, error: String) -> Result<(), String> {
+        match on_error {
+            OnError::Stop => Err(format!("line {}: {}", self.lines, error)),
+            OnError::Skip => {
+                self.skipped += 1;
+                if self.errors.len() < MAX_REPORTED_UPLOAD_ERRORS {
+                    self.errors.push(json!({"line": self.lines, "error": error}));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The layout of the JSON responses of the read endpoints: compact by default, as most clients
+/// are programs, and indented only when asked for with `?pretty=true` or an `Accept` header
+/// with a `pretty=true` parameter (e.g. `Accept: application/json; pretty=true`).
+/// `?pretty=false` always selects the compact layout.
+///
+/// Extracting it fails with `invalid_query` if `pretty` is not a boolean.
+#[derive(Clone, Copy)]
+struct JsonFormat {
+    pretty: bool,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for JsonFormat {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<FormatParams>::try_from_uri(&parts.uri)?;
+        let pretty = params.pretty.unwrap_or_else(|| {
+            parts.headers.get_all(header::ACCEPT).iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split([',', ';']))
+                .any(|parameter| parameter.trim().eq_ignore_ascii_case("pretty=true"))
+        });
+
+        Ok(JsonFormat { pretty })
+    }
+}
+
+impl JsonFormat {
+    /// Serializes `value` in this layout into an `application/json` response with `status`.
+    fn respond<T: Serialize>(self, status: StatusCode, value: &T) -> Response {
+        let body = if self.pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        };
+
+        (status, [(header::CONTENT_TYPE, "application/json")], body.unwrap()).into_response()
+    }
+}
+
+/// Returns the `X-Request-Id` header value used to correlate the log messages of a request,
+/// or `-` if the header is missing or not valid text.
+pub fn request_id(headers: &HeaderMap) -> &str {
+    headers.get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-")
+}
+
+/// Middleware that rejects requests without a valid `Authorization: Bearer <api_key>` header.
+/// `GET /health` and `GET /version` are always allowed, so that probes and deploy checks keep
+/// working without the key, and so are the API description, the order schema, and the Swagger UI,
+/// which browsers and producers' tooling open without a token.
+///
+/// # Returns:
+/// - The response of the inner handler if the token matches `api_key` or the route is public.
+/// - `StatusCode::UNAUTHORIZED` with `{"error": "..."}` and a `WWW-Authenticate` header otherwise.
+pub async fn require_api_key(State(api_key): State<Arc<str>>, request: Request, next: Next) -> Response {
+    if matches!(request.uri().path(), "/health" | "/version" | "/openapi.json" | "/schema/order" | "/docs") {
+        return next.run(request).await;
+    }
+
+    let token = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match token {
+        // Compared in constant time, so that the time it takes to reject a token doesn't tell how
+        // many of its leading bytes match the key.
+        Some(token) if token.as_bytes().ct_eq(api_key.as_bytes()).into() => next.run(request).await,
+        _ => {
+            warn!("request_id={} Rejected unauthenticated request to {}", request_id(request.headers()), request.uri());
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(json!({"error": "missing or invalid API key"})),
+            ).into_response()
+        }
+    }
+}
+
+/// Middleware that makes `POST /order` idempotent for clients sending an `Idempotency-Key` header,
+/// protecting against retries before the body is even read. The response to the first request with
+/// a key is kept (see `IdempotencyCache`) and returned for every repeat with `Idempotent-Replayed: true`.
+/// Only successful responses are kept: after an error nothing was queued, so a retry, e.g. with a
+/// corrected body, is processed again.
+///
+/// # Returns:
+/// - The response of the inner handler for a request without a key or with a new one.
+/// - The original response with `Idempotent-Replayed: true` for a repeated key.
+/// - An `ApiError` otherwise, as from the handler of `POST /order`:
+///   - `conflict` if a request with the key is still being processed;
+///   - `invalid_query` if the key is empty, longer than `MAX_IDEMPOTENCY_KEY_LENGTH`, or not printable ASCII.
+pub async fn idempotent_submissions(State(state): State<AppStateType>, request: Request, next: Next) -> Response {
+    if request.method() != Method::POST || request.uri().path() != "/order" {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH => key.to_string(),
+        _ => {
+            return ApiError::InvalidParameter(format!(
+                "Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LENGTH} printable ASCII characters"
+            ))
+            .into_response();
+        }
+    };
+    let request_id = request_id(request.headers()).to_string();
+
+    let pending = match state.idempotency_keys().reserve(&key) {
+        Reservation::Pending(pending) => pending,
+        Reservation::Replay(stored) => {
+            info!("request_id={} Replaying the response to Idempotency-Key {}", request_id, key);
+            let mut response = (stored.status, stored.headers, stored.body).into_response();
+            response.headers_mut().insert("idempotent-replayed", HeaderValue::from_static("true"));
+            return response;
+        }
+        Reservation::InFlight => {
+            warn!("request_id={} A request with Idempotency-Key {} is still in progress", request_id, key);
+            return ApiError::Conflict("a request with this Idempotency-Key is still in progress".to_string()).into_response();
+        }
+    };
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        pending.complete(None);
+        return response;
+    }
+
+    // The responses of `POST /order` are small JSON documents, so buffering them is cheap.
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => {
+            pending.complete(Some(StoredResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            }));
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => {
+            cry!("request_id={} Failed to read the response to Idempotency-Key {}: {}", request_id, key, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Middleware that limits the rate of `POST` and `PUT` requests from every client IP address.
+/// Read requests are not limited. Clients connected over a Unix socket have no IP address and
+/// share a single limit.
+///
+/// # Returns:
+/// - The response of the inner handler if the request is allowed.
+/// - `StatusCode::TOO_MANY_REQUESTS` with `{"error": "..."}` and a `Retry-After` header
+///   (in whole seconds) if the client exceeded the limit.
+pub async fn rate_limit_writes(
+    State(limiter): State<Arc<RateLimiter>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::POST && request.method() != Method::PUT {
+        return next.run(request).await;
+    }
+
+    let peer = connect_info.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ConnectInfo(peer)| peer.ip());
+    match limiter.check(peer) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            warn!("request_id={} Rate limit exceeded by {}", request_id(request.headers()), peer);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.as_secs_f64().ceil().max(1.0).to_string())],
+                Json(json!({"error": "too many requests"})),
+            ).into_response()
+        }
+    }
+}
+
+/// Builds the CORS layer allowing browser clients from `origins` to call the API.
+/// A single `*` allows any origin. Preflight `OPTIONS` requests are answered by the layer itself.
+pub fn cors_layer(origins: &[HeaderValue]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().cloned())
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::HeaderName::from_static("x-request-id"),
+            header::HeaderName::from_static("x-dry-run"),
+            header::HeaderName::from_static(IDEMPOTENCY_KEY),
+        ])
+        .expose_headers([
+            header::LOCATION,
+            header::RETRY_AFTER,
+            header::HeaderName::from_static("x-request-id"),
+            header::HeaderName::from_static("x-cache"),
+            header::HeaderName::from_static("x-duplicate"),
+            header::HeaderName::from_static("idempotent-replayed"),
+        ])
 }
@@ -0,0 +1,58 @@
+use crate::order::Order;
+use crate::state::AppState;
+use log::{info, warn};
+use serde_json::Value;
+use std::path::Path;
+
+/// Queues the orders from `path`, a JSON array of `Order`s, as if they were submitted through
+/// `POST /orders`. Used by `--seed-file` to fill the service with data for local testing and demos.
+///
+/// Seeding never prevents the service from starting: entries that are not valid orders are
+/// skipped with a warning, and so is the whole file if it can't be read or is not a JSON array.
+///
+/// # Parameters
+/// - `state`: The application state the orders are added to.
+/// - `path`: The file containing the orders.
+///
+/// # Returns
+/// The number of queued orders.
+pub async fn load(state: &AppState, path: &Path) -> usize {
+    let entries = match std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str::<Vec<Value>>(&contents).map_err(|e| e.to_string()))
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Skipping the seed file {}: {}", path.display(), e);
+            return 0;
+        }
+    };
+
+    let total = entries.len();
+    let mut loaded = 0;
+    for (index, entry) in entries.into_iter().enumerate() {
+        let mut order: Order = match serde_json::from_value(entry) {
+            Ok(order) => order,
+            Err(e) => {
+                warn!("Skipping seed order #{}: {}", index, e);
+                continue;
+            }
+        };
+        if state.normalize_input() {
+            order.normalize();
+        }
+        if let Err(violations) = order.validate(state.validation_rules()) {
+            warn!("Skipping seed order #{} ({}): {}", index, order.order_uid, violations.join("; "));
+            continue;
+        }
+
+        let order_uid = order.order_uid.clone();
+        match state.add_order(order).await {
+            Ok(_) => loaded += 1,
+            Err(e) => warn!("Skipping seed order #{} ({}): {}", index, order_uid, e),
+        }
+    }
+
+    info!("Loaded {} of {} orders from the seed file {}", loaded, total, path.display());
+    loaded
+}
@@ -0,0 +1,93 @@
+use crate::order::Order;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// A copy of the orders of the queue that are not saved to the database yet, written to disk
+/// periodically, so that a crash loses at most the orders accepted since the last snapshot
+/// without paying for a sync on every order like the `Journal`.
+///
+/// The snapshot is written to a temporary file that then replaces the previous one, so a crash
+/// while writing leaves the previous snapshot behind.
+/// - `path`: The location of the snapshot file.
+pub struct Snapshot {
+    path: PathBuf,
+}
+
+/// The contents of the snapshot file. `Order::received_at` is not deserialized from request
+/// bodies, so it is kept next to every order.
+#[derive(Serialize)]
+struct ContentsRef<'a> {
+    taken_at: DateTime<Utc>,
+    orders: Vec<EntryRef<'a>>,
+}
+
+/// An order of the snapshot file.
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    received_at: Option<DateTime<Utc>>,
+    order: &'a Order,
+}
+
+/// The contents of the snapshot file as read back by `Snapshot::read`.
+#[derive(Deserialize)]
+struct Contents {
+    taken_at: DateTime<Utc>,
+    orders: Vec<Entry>,
+}
+
+/// An order of the snapshot file as read back by `Snapshot::read`.
+#[derive(Deserialize)]
+struct Entry {
+    received_at: Option<DateTime<Utc>>,
+    order: Order,
+}
+
+impl Snapshot {
+    /// Creates a snapshot kept at `path`; nothing is read or written yet.
+    pub fn new(path: &Path) -> Self {
+        Snapshot { path: path.to_path_buf() }
+    }
+
+    /// Serializes `pending`, the orders that are not saved to the database yet, into the
+    /// contents of the snapshot file. Cheap enough to be done while the queue is locked.
+    pub fn serialize<'a>(pending: impl Iterator<Item = &'a Order>) -> serde_json::Result<Vec<u8>> {
+        let orders = pending.map(|order| EntryRef { received_at: order.received_at, order }).collect();
+        serde_json::to_vec(&ContentsRef { taken_at: Utc::now(), orders })
+    }
+
+    /// Replaces the snapshot file with `contents`, as returned by `Snapshot::serialize`, and waits
+    /// until it is written to disk.
+    pub fn write(&self, contents: &[u8]) -> io::Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(contents)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_data()?;
+
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    /// Reads the snapshot file, if there is one.
+    ///
+    /// # Returns
+    /// `Ok(Some((taken_at, orders)))` with the time the snapshot was taken and its orders,
+    /// `Ok(None)` if there is no snapshot file, or an `io::Error` if it can't be read or parsed.
+    pub fn read(&self) -> io::Result<Option<(DateTime<Utc>, Vec<Order>)>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let contents: Contents = serde_json::from_reader(io::BufReader::new(file))?;
+        let orders = contents.orders.into_iter()
+            .map(|Entry { received_at, mut order }| {
+                order.received_at = received_at;
+                order
+            })
+            .collect();
+        Ok(Some((contents.taken_at, orders)))
+    }
+}
@@ -0,0 +1,87 @@
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, Uri};
+use std::time::Duration;
+use log::{debug, warn, error as cry};
+use crate::order::Order;
+
+/// How many times a notification is sent before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry; every following retry waits twice as long.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long a single attempt may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Notifies a downstream system about persisted orders by posting them to a URL.
+/// - `url`: The `http://` URL the orders are posted to.
+/// - `client`: The HTTP client, shared by all notifications.
+pub struct Webhook {
+    url: Uri,
+    client: Client<HttpConnector>,
+}
+
+impl Webhook {
+    /// Creates a webhook posting to `url`.
+    pub fn new(url: Uri) -> Self {
+        Webhook {
+            url,
+            client: Client::new(),
+        }
+    }
+
+    /// Posts `order` as JSON to the webhook URL from a background task, so that the caller
+    /// isn't blocked. Connection errors and `5xx` responses are retried with exponential
+    /// backoff up to `MAX_ATTEMPTS` times; failures are only logged.
+    pub fn notify(&self, order: &Order) {
+        let url = self.url.clone();
+        let client = self.client.clone();
+        let order_uid = order.order_uid.clone();
+        let body = serde_json::to_vec(order).expect("Orders are always serializable");
+
+        tokio::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+
+            for attempt in 1..=MAX_ATTEMPTS {
+                let request = Request::builder()
+                    .method(Method::POST)
+                    .uri(url.clone())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.clone()))
+                    .expect("Webhook request is always valid");
+
+                let error = match tokio::time::timeout(REQUEST_TIMEOUT, client.request(request)).await {
+                    Ok(Ok(response)) if response.status().is_success() => {
+                        debug!("Notified the webhook about order {}", order_uid);
+                        return;
+                    }
+                    Ok(Ok(response)) if !response.status().is_server_error() => {
+                        cry!("Webhook rejected order {} with {}", order_uid, response.status());
+                        return;
+                    }
+                    Ok(Ok(response)) => format!("status {}", response.status()),
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => format!("no response in {:?}", REQUEST_TIMEOUT),
+                };
+
+                if attempt == MAX_ATTEMPTS {
+                    cry!("Failed to notify the webhook about order {} after {} attempts: {}", order_uid, attempt, error);
+                } else {
+                    warn!("Failed to notify the webhook about order {} ({}), retrying in {:?}", order_uid, error, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        });
+    }
+}
+
+/// Parses the `--webhook-url` argument, accepting only `http://` URLs.
+pub fn parse_url(url: &str) -> Result<Uri, String> {
+    let url: Uri = url.parse().map_err(|e| format!("invalid URL: {e}"))?;
+
+    match url.scheme_str() {
+        Some("http") if url.host().is_some() => Ok(url),
+        Some("http") => Err("the URL has no host".to_string()),
+        _ => Err("only http:// URLs are supported".to_string()),
+    }
+}
@@ -0,0 +1,282 @@
+//! Tests of the HTTP API without a database: the service runs with `--no-db`, so that the
+//! orders are stored in memory, and requests are sent through the router.
+
+mod common;
+
+use axum::body::Body;
+use axum::middleware;
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use common::{app, get, json_request, order, post, send};
+use std::sync::Arc;
+use wb_rest_order::routes;
+
+#[tokio::test]
+async fn valid_order_is_accepted() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = post(&router, "/order", &order("valid")).await;
+
+    assert_eq!(response.status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn malformed_order_is_rejected_with_the_parse_error() {
+    let (router, _) = app(&["--no-db"]).await;
+    let mut order = order("malformed");
+    order["payment"]["amount"] = "1817".into();
+
+    let response = post(&router, "/order", &order).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_body");
+    let message = response.body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("invalid type: string \"1817\""), "unexpected message: {message}");
+}
+
+#[tokio::test]
+async fn order_without_items_is_rejected_and_not_stored() {
+    let (router, state) = app(&["--no-db"]).await;
+    let mut order = order("no-items");
+    order["items"] = serde_json::json!([]);
+
+    let response = post(&router, "/order", &order).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_order");
+    assert_eq!(response.body["error"]["details"][0], "items must not be empty");
+    assert_eq!(state.flush_all().await.unwrap(), 0);
+    assert_eq!(state.count_orders().await.unwrap(), 0);
+    assert_eq!(get(&router, "/order/no-items").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn resubmitted_order_is_queued_once() {
+    let (router, state) = app(&["--no-db"]).await;
+
+    let statuses = [
+        post(&router, "/order", &order("resubmitted")).await.status,
+        post(&router, "/order", &order("resubmitted")).await.status,
+        post(&router, "/order", &order("resubmitted")).await.status,
+    ];
+
+    assert_eq!(statuses, [StatusCode::CREATED, StatusCode::OK, StatusCode::OK]);
+    assert_eq!(get(&router, "/stats").await.body["queue_len"], 1);
+    assert_eq!(state.flush_all().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn latest_order_is_not_found_without_orders() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = get(&router, "/order").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["error"]["code"], "not_found");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn orders_are_accepted_while_the_full_queue_is_flushed() {
+    let (router, state) = app(&["--no-db", "--cache-size", "4"]).await;
+
+    let submissions: Vec<_> = (0..40)
+        .map(|i| {
+            let router = router.clone();
+            tokio::spawn(async move { post(&router, "/order", &order(&format!("concurrent-{i}"))).await.status })
+        })
+        .collect();
+    for submission in submissions {
+        assert_eq!(submission.await.unwrap(), StatusCode::CREATED);
+    }
+
+    state.flush_all().await.unwrap();
+    assert_eq!(state.count_orders().await.unwrap(), 40);
+}
+
+#[tokio::test]
+async fn flushed_orders_are_stored_in_the_order_they_were_received() {
+    // The store keeps the 5 orders saved last, so these must be the 5 submitted last.
+    let (router, state) = app(&["--no-db", "--cache-size", "3", "--memory-store-size", "5"]).await;
+
+    for i in 0..10 {
+        assert_eq!(post(&router, "/order", &order(&format!("ordered-{i}"))).await.status, StatusCode::CREATED);
+    }
+    state.flush_all().await.unwrap();
+
+    for i in 0..10 {
+        let expected = if i < 5 { StatusCode::NOT_FOUND } else { StatusCode::OK };
+        assert_eq!(get(&router, &format!("/order/ordered-{i}")).await.status, expected, "ordered-{i}");
+    }
+}
+
+#[tokio::test]
+async fn batch_that_is_not_an_array_is_an_invalid_body() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = post(&router, "/orders", &order("batch")).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn batch_is_refused_in_read_only_mode() {
+    let (router, _) = app(&["--no-db", "--read-only"]).await;
+
+    let response = post(&router, "/orders", &serde_json::json!([order("batch")])).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["error"]["code"], "read_only");
+}
+
+#[tokio::test]
+async fn put_replaces_the_items_of_an_order() {
+    let (router, _) = app(&["--no-db"]).await;
+    post(&router, "/order", &order("corrected")).await;
+    let mut corrected = order("corrected");
+    let mut second_item = corrected["items"][0].clone();
+    second_item["chrt_id"] = 9934931.into();
+    corrected["items"].as_array_mut().unwrap().push(second_item);
+    corrected["payment"]["goods_total"] = 634.into();
+
+    let response = send(&router, json_request(Method::PUT, "/order/corrected", &corrected)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let items = get(&router, "/order/corrected/items").await.body;
+    assert_eq!(items, corrected["items"]);
+}
+
+#[tokio::test]
+async fn put_of_an_unknown_order_is_not_found() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = send(&router, json_request(Method::PUT, "/order/unknown", &order("unknown"))).await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn put_with_another_uid_in_the_body_is_a_conflict() {
+    let (router, _) = app(&["--no-db"]).await;
+    post(&router, "/order", &order("corrected")).await;
+
+    let response = send(&router, json_request(Method::PUT, "/order/corrected", &order("other"))).await;
+
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert_eq!(response.body["error"]["code"], "conflict");
+}
+
+#[tokio::test]
+async fn put_of_an_invalid_order_is_an_invalid_order() {
+    let (router, _) = app(&["--no-db"]).await;
+    post(&router, "/order", &order("corrected")).await;
+    let mut corrected = order("corrected");
+    corrected["payment"]["amount"] = (-1).into();
+
+    let response = send(&router, json_request(Method::PUT, "/order/corrected", &corrected)).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_order");
+}
+
+#[tokio::test]
+async fn invalid_search_is_an_invalid_query() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = get(&router, "/orders/search?q=ab").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_query");
+    assert_eq!(response.body["error"]["message"], "q must be 3 to 100 characters long");
+}
+
+#[tokio::test]
+async fn reversed_window_is_an_invalid_query() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    let response = get(&router, "/orders?from=2024-02-01T00:00:00Z&to=2024-01-01T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_query");
+    assert_eq!(response.body["error"]["message"], "from must not be after to");
+}
+
+#[tokio::test]
+async fn unknown_order_and_its_items_are_not_found() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    for uri in ["/order/unknown", "/order/unknown/items"] {
+        let response = get(&router, uri).await;
+
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "{uri}");
+        assert_eq!(response.body["error"]["code"], "not_found", "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn non_positive_limits_are_invalid_queries() {
+    let (router, _) = app(&["--no-db"]).await;
+
+    for (uri, message) in [
+        ("/orders/latest/0", "n must be a positive number"),
+        ("/orders/by-customer/test?limit=0", "limit must be positive"),
+    ] {
+        let response = get(&router, uri).await;
+
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(response.body["error"]["code"], "invalid_query", "{uri}");
+        assert_eq!(response.body["error"]["message"], message, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn cors_preflight_allows_the_idempotency_key() {
+    let (router, _) = app(&["--no-db"]).await;
+    let router = router.layer(routes::cors_layer(&[HeaderValue::from_static("https://shop.example")]));
+    let preflight = Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/order")
+        .header(header::ORIGIN, "https://shop.example")
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type,idempotency-key")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = send(&router, preflight).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let allowed = response.headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap();
+    assert!(allowed.split(',').any(|name| name.trim() == "idempotency-key"), "allowed headers: {allowed}");
+}
+
+#[tokio::test]
+async fn api_key_must_match_exactly() {
+    let (router, _) = app(&["--no-db"]).await;
+    let api_key: Arc<str> = Arc::from("secret-key");
+    let router = router.route_layer(middleware::from_fn_with_state(api_key, routes::require_api_key));
+    let with_token = |token: &str| {
+        Request::builder()
+            .uri("/stats")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    assert_eq!(send(&router, with_token("secret-key")).await.status, StatusCode::OK);
+    for wrong in ["secret-kez", "secret", "secret-key-2", ""] {
+        assert_eq!(send(&router, with_token(wrong)).await.status, StatusCode::UNAUTHORIZED, "{wrong:?}");
+    }
+}
+
+#[tokio::test]
+async fn invalid_idempotency_key_is_an_api_error() {
+    let (router, state) = app(&["--no-db"]).await;
+    let router = router.layer(middleware::from_fn_with_state(state, routes::idempotent_submissions));
+    let mut request = json_request(Method::POST, "/order", &order("keyed"));
+    request.headers_mut().insert("idempotency-key", HeaderValue::from_static(""));
+
+    let response = send(&router, request).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["error"]["code"], "invalid_query");
+}
@@ -0,0 +1,83 @@
+//! Helpers shared by the integration tests: the state and the router are built from command-line
+//! arguments like `main.rs` builds them, and requests are sent through the router with `oneshot`.
+#![allow(dead_code)]
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use clap::Parser;
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+use wb_rest_order::cli::CLIArgs;
+use wb_rest_order::routes;
+use wb_rest_order::state::AppState;
+
+/// A valid order, as in the README.
+const ORDER: &str = include_str!("../fixtures/order.json");
+
+/// Builds the state and the router from `args`, given as on the command line; without
+/// `--database-url`, pass `--no-db`.
+pub async fn app(args: &[&str]) -> (Router, Arc<AppState>) {
+    let args = CLIArgs::parse_from(["wb-rest-order"].iter().chain(args));
+    let database = args.resolve_database().expect("invalid database arguments");
+    let state = AppState::new(&args.app_state_config(database)).await.expect("failed to create the state");
+    let state = Arc::new(state);
+
+    (Router::new().merge(routes::handle_order()).with_state(state.clone()), state)
+}
+
+/// Returns the valid sample order with `order_uid` and `payment.transaction` set to `uid`.
+pub fn order(uid: &str) -> Value {
+    let mut order: Value = serde_json::from_str(ORDER).unwrap();
+    order["order_uid"] = uid.into();
+    order["payment"]["transaction"] = uid.into();
+    order
+}
+
+/// A response with the body parsed as JSON, or kept as a string if it is not JSON
+/// (`Value::Null` if it is empty).
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+/// Sends `request` through `router`.
+pub async fn send(router: &Router, request: Request<Body>) -> TestResponse {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    let body = match serde_json::from_slice(&bytes) {
+        Ok(body) => body,
+        Err(_) if bytes.is_empty() => Value::Null,
+        Err(_) => Value::String(String::from_utf8_lossy(&bytes).into_owned()),
+    };
+
+    TestResponse { status: parts.status, headers: parts.headers, body }
+}
+
+/// Builds a request without a body.
+pub fn request(method: Method, uri: &str) -> Request<Body> {
+    Request::builder().method(method).uri(uri).body(Body::empty()).unwrap()
+}
+
+/// Builds a request with `body` as JSON.
+pub fn json_request(method: Method, uri: &str, body: &Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Sends `GET uri`.
+pub async fn get(router: &Router, uri: &str) -> TestResponse {
+    send(router, request(Method::GET, uri)).await
+}
+
+/// Sends `POST uri` with `body` as JSON.
+pub async fn post(router: &Router, uri: &str, body: &Value) -> TestResponse {
+    send(router, json_request(Method::POST, uri, body)).await
+}
@@ -0,0 +1,79 @@
+//! Tests against a real PostgreSQL database. They are ignored by default, so that `cargo test`
+//! needs no database; to run them, point `DATABASE_URL` at a database the tests may create
+//! schemas in and run `cargo test --test db -- --ignored`. Every test works in a schema of its
+//! own, which is dropped once the test passes and kept for inspection if it fails.
+
+mod common;
+
+use axum::http::StatusCode;
+use tokio_postgres::{Client, NoTls};
+
+/// Returns `DATABASE_URL`.
+fn database_url() -> String {
+    std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a PostgreSQL database")
+}
+
+/// Connects to the database outside of the service, to look at the tables directly.
+async fn connect() -> Client {
+    let (client, connection) = tokio_postgres::connect(&database_url(), NoTls).await.expect("failed to connect");
+    tokio::spawn(connection);
+    client
+}
+
+/// Returns a schema name no other test run uses.
+fn unique_schema() -> String {
+    format!("it_{}", uuid::Uuid::new_v4().simple())
+}
+
+#[tokio::test]
+#[ignore = "needs a PostgreSQL database in DATABASE_URL"]
+async fn posted_order_is_written_to_all_tables() {
+    let url = database_url();
+    let schema = unique_schema();
+    let (router, state) = common::app(&["--database-url", &url, "--schema", &schema, "--flush-interval-secs", "0"]).await;
+
+    let mut order = common::order("it-order");
+    let mut second_item = order["items"][0].clone();
+    second_item["chrt_id"] = 9934931.into();
+    order["items"].as_array_mut().unwrap().push(second_item);
+    order["payment"]["goods_total"] = 634.into();
+
+    let response = common::post(&router, "/order", &order).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+    assert_eq!(state.flush_all().await.unwrap(), 1);
+
+    let client = connect().await;
+    let count = |table: &str, column: &str| format!("SELECT count(*) FROM {schema}.{table} WHERE {column} = 'it-order'");
+    for (table, column, expected) in [
+        ("orders", "order_uid", 1),
+        ("deliveries", "order_uid", 1),
+        ("payments", "transaction_id", 1),
+        ("items", "order_uid", 2),
+    ] {
+        let rows: i64 = client.query_one(&count(table, column), &[]).await.unwrap().get(0);
+        assert_eq!(rows, expected, "rows of the order in {table}");
+    }
+
+    // A fresh instance doesn't have the order queued, so it reassembles it from the tables.
+    let (router, _) = common::app(&["--database-url", &url, "--schema", &schema, "--warm-cache-size", "0"]).await;
+    let response = common::get(&router, "/order/it-order").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let mut stored = response.body;
+    stored.as_object_mut().unwrap().remove("received_at");
+    assert_eq!(stored, order);
+
+    client.batch_execute(&format!("DROP SCHEMA {schema} CASCADE")).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs a PostgreSQL database in DATABASE_URL"]
+async fn latest_order_is_unavailable_while_the_tables_cant_be_read() {
+    // Without migrations the schema has no tables, so the stored orders can't be read.
+    let schema = unique_schema();
+    let (router, _) = common::app(&["--database-url", &database_url(), "--schema", &schema, "--skip-migrations"]).await;
+
+    let response = common::get(&router, "/order").await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{}", response.body);
+    assert_eq!(response.body["error"]["code"], "database_unavailable");
+}